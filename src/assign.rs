@@ -0,0 +1,487 @@
+use crate::map::Feature;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// A proposed set of FIPS codes for one precinct, written to precinct-conversions#precincts.
+#[derive(Clone)]
+pub struct Assignment {
+    pub fips: Vec<String>,
+    pub confidence: f64,
+}
+
+impl Assignment {
+    /// Anything below this is highlighted in the precincts sheet for manual review.
+    pub const REVIEW_BELOW: f64 = 0.75;
+
+    /// The most a name match can score when the name alone can't tell the municipality apart.
+    const UNCERTAIN: f64 = 0.7;
+}
+
+struct Candidate {
+    tokens: Vec<String>,
+    fips: String,
+    township: bool,
+}
+
+/// Proposes municipalities for precincts by matching SOS precinct names (e.g. `AKRON 1-A`,
+/// `BATH TWP A`) against the municipal names of the same county.
+pub struct NameMatcher {
+    municipals: HashMap<String, Vec<Candidate>>, // county code -> municipalities
+}
+
+impl NameMatcher {
+    /// `municipals` is (county code, name, fips, type) as written to municipal-codes.xlsx.
+    pub fn new<I: Iterator<Item = (String, String, String, String)>>(municipals: I) -> NameMatcher {
+        let mut by_county: HashMap<String, Vec<Candidate>> = HashMap::new();
+        for (county, name, fips, r#type) in municipals {
+            let name = name.trim_end_matches("(Township)").trim_end_matches("(City)");
+            by_county.entry(county).or_default().push(Candidate {
+                tokens: tokenize(name),
+                fips,
+                township: r#type == "township",
+            });
+        }
+
+        NameMatcher {
+            municipals: by_county,
+        }
+    }
+
+    /// The county code of the map for an SOS county name.
+    pub fn county_code(&self, county_name: &str) -> Option<&'static str> {
+        crate::counties::by_name(county_name).map(|county| county.code)
+    }
+
+    pub fn assign(&self, county_name: &str, precinct: &str) -> Option<Assignment> {
+        let code = self.county_code(county_name)?;
+        let candidates = self.municipals.get(code)?;
+
+        let mut tokens = tokenize(precinct);
+        // `PRECINCT <name>` precincts are named after their polling place's community, which is
+        // not necessarily the municipality they lie in (`PRECINCT RACINE` in Meigs)
+        let polling_place = tokens.first().is_some_and(|t| t == "PRECINCT");
+        if polling_place {
+            tokens.remove(0);
+        }
+        let hint = if tokens.iter().any(|t| TOWNSHIP_WORDS.contains(&t.as_str())) {
+            Some(true)
+        } else if tokens.iter().any(|t| CITY_WORDS.contains(&t.as_str())) {
+            Some(false)
+        } else {
+            None
+        };
+        tokens.retain(|t| !TOWNSHIP_WORDS.contains(&t.as_str()) && !CITY_WORDS.contains(&t.as_str()));
+        while tokens.first().is_some_and(|t| t.chars().all(|c| c.is_ascii_digit())) {
+            tokens.remove(0); // county precinct numbers, as in `PRECINCT 0035 WASHINGTON`
+        }
+
+        // the longest municipal name the precinct name begins with
+        let best = candidates.iter()
+            .filter(|c| !c.tokens.is_empty() && tokens.starts_with(&c.tokens))
+            .map(|c| c.tokens.len())
+            .max();
+
+        let Some(best) = best else {
+            return self.assign_loosely(candidates, &tokens, hint);
+        };
+
+        let matches: Vec<&Candidate> = candidates.iter().filter(|c| c.tokens.len() == best && tokens.starts_with(&c.tokens)).collect();
+        let hinted: Vec<&Candidate> = matches.iter().copied().filter(|c| Some(c.township) == hint).collect();
+        let mut confidence: f64 = match (matches.len(), hinted.len()) {
+            (_, 1) => 1.0,
+            (1, _) if hint.is_none() => 0.9,
+            (1, _) => 0.6, // the name matched but the TWP/CITY marker disagrees
+            _ => 0.5,
+        };
+        // `PERRY TWP A` may be a precinct of Perry village as well as of Perry township, since
+        // villages are often named after the township they lie in
+        let shared = hint == Some(true) && matches.iter().any(|c| c.township) && matches.iter().any(|c| !c.township);
+        if polling_place || shared {
+            confidence = confidence.min(Assignment::UNCERTAIN);
+        }
+
+        Some(Assignment {
+            fips: vec![pick(&matches, hint)?.fips.clone()],
+            confidence,
+        })
+    }
+}
+
+impl NameMatcher {
+    /// Fallbacks for names that don't begin with a municipal name, all of which need review:
+    /// the first word abbreviating a municipality of the county (`COLS 13-B`, `CUY FALLS 7-C`),
+    /// then a city of another county the precinct spills into (`WESTERVILLE CITY A` in Delaware).
+    fn assign_loosely(&self, candidates: &[Candidate], tokens: &[String], hint: Option<bool>) -> Option<Assignment> {
+        let first = tokens.first()?;
+        let abbreviated: Vec<&Candidate> = candidates.iter()
+            .filter(|c| first.len() >= 3 && c.tokens.first().is_some_and(|t| is_abbreviation(first, t)))
+            .collect();
+        if let Some(candidate) = pick(&abbreviated, hint) {
+            return Some(Assignment {
+                fips: vec![candidate.fips.clone()],
+                confidence: if abbreviated.len() == 1 { 0.5 } else { 0.25 },
+            });
+        }
+
+        let elsewhere: Vec<&Candidate> = self.municipals.values()
+            .flatten()
+            .filter(|c| !c.township && !c.tokens.is_empty() && tokens.starts_with(&c.tokens))
+            .collect();
+        match elsewhere.as_slice() {
+            [candidate] => Some(Assignment {
+                fips: vec![candidate.fips.clone()],
+                confidence: 0.5,
+            }),
+            _ => None
+        }
+    }
+}
+
+/// Assigns precincts from a precinct boundary map by overlaying it with the municipals map.
+/// Precincts are keyed by the county code of the municipality they overlap most and their name.
+pub struct SpatialMatcher {
+    assignments: HashMap<(String, String), Assignment>,
+}
+
+impl SpatialMatcher {
+    /// Every municipality covering more than `min_overlap` of a precinct's area is assigned to it,
+    /// largest first; the confidence is the share of the precinct those municipalities cover.
+    pub fn new(precinct_maps: &[PathBuf], municipals: &[Feature], name_field: &str, min_overlap: f64) -> Result<SpatialMatcher, String> {
+        use colored::Colorize;
+        use geo::{Area, BooleanOps};
+
+        let mut assignments: HashMap<(String, String), Assignment> = HashMap::new();
+        for path in precinct_maps {
+            let precincts = crate::map::read_features(path)?;
+            for precinct in precincts {
+                let Some(name) = precinct.field(name_field) else {
+                    return Err(format!("Precinct in {} is missing field {}: {:#?}", path.display().to_string().underline(), name_field.underline(), precinct.record));
+                };
+
+                let area = precinct.geometry.unsigned_area();
+                if area <= 0.0 {
+                    continue;
+                }
+
+                let mut overlaps: Vec<(f64, String, String)> = municipals.iter()
+                    .filter(|municipal| municipal.may_intersect(&precinct))
+                    .filter_map(|municipal| {
+                        let overlap = municipal.geometry.intersection(&precinct.geometry).unsigned_area() / area;
+                        Some((overlap, municipal.field("fips")?, municipal.field("county")?))
+                    })
+                    .filter(|(overlap, _, _)| *overlap > 0.0)
+                    .collect();
+                overlaps.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+                let Some((_, _, county)) = overlaps.first().cloned() else { continue };
+                let kept: Vec<&(f64, String, String)> = overlaps.iter().filter(|(overlap, _, _)| *overlap > min_overlap).collect();
+                if kept.is_empty() {
+                    continue;
+                }
+
+                let assignment = assignments.entry((county, tokenize(&name).join(" "))).or_insert(Assignment {
+                    fips: Vec::new(),
+                    confidence: 0.0,
+                });
+
+                // a precinct split into several features accumulates the codes of each part
+                for (_, fips, _) in &kept {
+                    if !assignment.fips.contains(fips) {
+                        assignment.fips.push(fips.clone());
+                    }
+                }
+                let covered: f64 = kept.iter().map(|(overlap, _, _)| overlap).sum();
+                assignment.confidence = assignment.confidence.max(covered.min(1.0));
+            }
+        }
+
+        Ok(SpatialMatcher { assignments })
+    }
+
+    pub fn assign(&self, county_code: &str, precinct: &str) -> Option<&Assignment> {
+        self.assignments.get(&(county_code.to_string(), tokenize(precinct).join(" ")))
+    }
+}
+
+const TOWNSHIP_WORDS: &[&str] = &["TWP", "TOWNSHIP", "TW", "TS"];
+const CITY_WORDS: &[&str] = &["CITY", "CTY", "VILLAGE", "VILL", "VIL", "VLG"];
+
+/// Prefers the municipality whose type agrees with the precinct's TWP/CITY marker, then cities,
+/// since SOS names township precincts with an explicit `TWP` far more often than city ones.
+fn pick<'a>(matches: &[&'a Candidate], hint: Option<bool>) -> Option<&'a Candidate> {
+    matches.iter().copied()
+        .find(|c| Some(c.township) == hint)
+        .or_else(|| matches.iter().copied().find(|c| !c.township))
+        .or_else(|| matches.first().copied())
+}
+
+fn tokenize(s: &str) -> Vec<String> {
+    s.to_uppercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| match t {
+            "ST" => "SAINT".to_string(),
+            "MT" => "MOUNT".to_string(),
+            "FT" => "FORT".to_string(),
+            "N" => "NORTH".to_string(),
+            "S" => "SOUTH".to_string(),
+            "E" => "EAST".to_string(),
+            "W" => "WEST".to_string(),
+            t => t.to_string(),
+        })
+        .collect()
+}
+
+/// Whether the letters of `code` appear in order in `name`, starting with its first (`ATB` for ASHTABULA).
+fn is_abbreviation(code: &str, name: &str) -> bool {
+    let mut name = name.chars();
+    let mut code = code.chars();
+    match (code.next(), name.next()) {
+        (Some(a), Some(b)) if a == b => {},
+        _ => return false,
+    }
+
+    code.all(|c| name.any(|n| n == c))
+}
+
+/// The precinct assignments of a previous election, keyed by (county name, precinct name), so
+/// precincts keeping their name between elections keep their municipalities.
+pub struct PriorAssignments {
+    precincts: HashMap<(String, String), Vec<String>>,
+}
+
+/// A precinct that is not in both elections, listed in precinct-conversions#changes.
+pub struct Change {
+    pub status: &'static str, // new, removed or renamed
+    pub county: String,
+    pub precinct: String,
+    pub previous: Option<String>, // the name in the previous election of a renamed precinct
+    pub fips: Vec<String>, // the previous assignment
+}
+
+impl PriorAssignments {
+    /// `source` is the id of an imported election, or an election directory or conversion file.
+    pub fn load(source: &str, workspace: &crate::workspace::Workspace) -> Result<PriorAssignments, String> {
+        let path = workspace.path(source);
+        match source.parse::<i64>() {
+            Ok(id) if !path.exists() => PriorAssignments::from_database(&workspace.db, id),
+            _ if path.is_dir() => PriorAssignments::from_workbook(&path.join("precinct-conversions.xlsx")),
+            _ => PriorAssignments::from_workbook(&path),
+        }
+    }
+
+    fn from_workbook(path: &std::path::Path) -> Result<PriorAssignments, String> {
+        use calamine::Reader;
+        use colored::Colorize;
+
+        let failed = |why: String| format!("Failed to read {}: {}", path.display().to_string().underline(), why);
+        let mut workbook = calamine::open_workbook_auto(path).map_err(|why| failed(why.to_string()))?;
+        let sheet = workbook.worksheet_range("precincts").map_err(|why| failed(why.to_string()))?;
+
+        let mut precincts = HashMap::new();
+        for row in sheet.rows() {
+            let [county, name, codes @ ..] = row else { continue };
+            let fips: Vec<String> = codes.iter()
+                .map(|code| code.to_string())
+                .take_while(|code| !code.is_empty() && code.chars().all(|c| c.is_ascii_digit()))
+                .collect();
+            if !fips.is_empty() {
+                precincts.insert((county.to_string(), name.to_string()), fips);
+            }
+        }
+
+        Ok(PriorAssignments { precincts })
+    }
+
+    fn from_database(db: &std::path::Path, election_id: i64) -> Result<PriorAssignments, String> {
+        use colored::Colorize;
+
+        let conn = crate::database::connect(db).ok_or(format!("Failed to open the database to read election id={}", election_id))?;
+        let rows = conn.prepare("
+            SELECT c.name, p.name, m.fips FROM precinct p
+            JOIN county c ON p.countyId = c.id JOIN municipality m ON p.municipalId = m.id
+            WHERE c.electionId = ?1
+        ").and_then(|mut stmt| stmt.query_map([election_id], |row| Ok((row.get(0)?, row.get(1)?, row.get::<_, String>(2)?)))?.collect::<rusqlite::Result<Vec<(String, String, String)>>>())
+            .map_err(|why| format!("Failed to read the precincts of election id={}: {}", election_id, why.to_string().underline()))?;
+        if rows.is_empty() {
+            return Err(format!("No precincts were imported for election id={}", election_id));
+        }
+
+        // merged municipalities hold each of their codes
+        let precincts = rows.into_iter()
+            .map(|(county, name, fips)| ((county, name), fips.split(',').map(|code| code.to_string()).collect()))
+            .collect();
+        Ok(PriorAssignments { precincts })
+    }
+
+    /// The previous assignment, unless it names a municipality missing from `known_fips`.
+    pub fn assign(&self, county: &str, precinct: &str, known_fips: &std::collections::HashSet<String>) -> Option<Assignment> {
+        let fips = self.precincts.get(&(county.to_string(), precinct.to_string()))?;
+        fips.iter().all(|code| known_fips.contains(code)).then(|| Assignment {
+            fips: fips.clone(),
+            confidence: 1.0,
+        })
+    }
+
+    /// Precincts of `current` missing from the previous election and the reverse. A new precinct
+    /// whose name is within two edits of exactly one removed precinct of its county is taken to be
+    /// that precinct renamed.
+    pub fn changes(&self, current: &[(String, String)]) -> Vec<Change> {
+        let current_set: std::collections::HashSet<&(String, String)> = current.iter().collect();
+        let mut removed: Vec<&(String, String)> = self.precincts.keys().filter(|key| !current_set.contains(key)).collect();
+        removed.sort();
+
+        let mut changes = Vec::new();
+        let mut renamed: std::collections::HashSet<&(String, String)> = std::collections::HashSet::new();
+        for key in current.iter().filter(|key| !self.precincts.contains_key(*key)) {
+            let name = tokenize(&key.1).concat();
+            let similar: Vec<&&(String, String)> = removed.iter()
+                .filter(|old| old.0 == key.0 && !renamed.contains(**old) && edit_distance(&name, &tokenize(&old.1).concat()) <= 2)
+                .collect();
+
+            match similar.as_slice() {
+                [old] => {
+                    renamed.insert(**old);
+                    changes.push(Change { status: "renamed", county: key.0.clone(), precinct: key.1.clone(), previous: Some(old.1.clone()), fips: self.precincts[**old].clone() });
+                },
+                _ => changes.push(Change { status: "new", county: key.0.clone(), precinct: key.1.clone(), previous: None, fips: Vec::new() })
+            }
+        }
+
+        for old in removed.into_iter().filter(|old| !renamed.contains(*old)) {
+            changes.push(Change { status: "removed", county: old.0.clone(), precinct: old.1.clone(), previous: None, fips: self.precincts[old].clone() });
+        }
+
+        changes
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            current.push((previous[j] + (ca != *cb) as usize).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher() -> NameMatcher {
+        let municipals = [
+            ("SUM", "Akron (City)", "3915301000", "city"),
+            ("SUM", "Bath (Township)", "3915304416", "township"),
+            ("SUM", "Copley (Township)", "3915318014", "township"),
+            ("SUM", "Cuyahoga Falls (City)", "3915319778", "city"),
+            ("SUM", "Fairlawn (City)", "3915325704", "city"),
+            ("FRA", "Columbus (City)", "3904918000", "city"),
+            ("FRA", "Westerville (City)", "3904983342", "city"),
+            ("DEL", "Genoa (Township)", "3904129638", "township"),
+            ("LAK", "Perry (Township)", "3908561896", "township"),
+            ("LAK", "Perry (City)", "3908561882", "city"),
+            ("MEG", "Racine (City)", "3910565130", "city"),
+            ("MEG", "Sutton (Township)", "3910575488", "township"),
+            ("MUS", "Fultonham (City)", "3911929092", "city"),
+            ("MUS", "Newton (Township)", "3911955454", "township"),
+            ("WAR", "Franklin (Township)", "3916528322", "township"),
+            ("WAR", "Franklin (City)", "3916528294", "city"),
+        ];
+
+        NameMatcher::new(municipals.into_iter().map(|(county, name, fips, r#type)| (county.to_string(), name.to_string(), fips.to_string(), r#type.to_string())))
+    }
+
+    fn assigned(county: &str, precinct: &str) -> Option<(Vec<String>, f64)> {
+        matcher().assign(county, precinct).map(|assignment| (assignment.fips, assignment.confidence))
+    }
+
+    #[test]
+    fn tokenize_expands_abbreviated_words() {
+        assert_eq!(tokenize("ST. MARYS CITY 1"), ["SAINT", "MARYS", "CITY", "1"]);
+        assert_eq!(tokenize("N Canton 2-A"), ["NORTH", "CANTON", "2", "A"]);
+        assert_eq!(tokenize("MT VERNON 3-B"), ["MOUNT", "VERNON", "3", "B"]);
+    }
+
+    #[test]
+    fn abbreviations_keep_letters_in_order() {
+        assert!(is_abbreviation("ATB", "ASHTABULA"));
+        assert!(is_abbreviation("COLS", "COLUMBUS"));
+        assert!(is_abbreviation("CUY", "CUYAHOGA"));
+        assert!(!is_abbreviation("BTA", "ASHTABULA"));
+        assert!(!is_abbreviation("TAB", "ASHTABULA"));
+    }
+
+    #[test]
+    fn edit_distance_counts_single_character_edits() {
+        assert_eq!(edit_distance("AKRON 1-B", "AKRON 1-B"), 0);
+        assert_eq!(edit_distance("AKRON 1-B", "AKRON 1-C"), 1);
+        assert_eq!(edit_distance("BATH TWP A", "BATH TWP"), 2);
+        assert_eq!(edit_distance("", "COPLEY"), 6);
+    }
+
+    #[test]
+    fn names_beginning_with_a_municipality_match_it() {
+        assert_eq!(assigned("Summit", "AKRON 1-A"), Some((vec!["3915301000".to_string()], 0.9)));
+        assert_eq!(assigned("Summit", "CUYAHOGA FALLS 7-C"), Some((vec!["3915319778".to_string()], 0.9)));
+    }
+
+    #[test]
+    fn township_markers_raise_or_lower_confidence() {
+        assert_eq!(assigned("Summit", "BATH TWP A"), Some((vec!["3915304416".to_string()], 1.0)));
+        assert_eq!(assigned("Summit", "COPLEY CITY B"), Some((vec!["3915318014".to_string()], 0.6)));
+    }
+
+    #[test]
+    fn ambiguous_names_need_review() {
+        // a township and a village of the same name
+        for precinct in ["PRECINCT PERRY TWP A", "PRECINCT PERRY TWP B", "PRECINCT PERRY TWP C"] {
+            assert_eq!(assigned("Lake", precinct), Some((vec!["3908561896".to_string()], 0.7)));
+        }
+        assert_eq!(assigned("Warren", "FRANKLIN TWP K"), Some((vec!["3916528322".to_string()], 0.7)));
+
+        // polling place names
+        assert_eq!(assigned("Meigs", "PRECINCT RACINE"), Some((vec!["3910565130".to_string()], 0.7)));
+        assert_eq!(assigned("Muskingum", "PRECINCT FULTONHAM"), Some((vec!["3911929092".to_string()], 0.7)));
+    }
+
+    #[test]
+    fn abbreviated_and_neighbouring_names_need_review() {
+        assert_eq!(assigned("Franklin", "COLS 13-B"), Some((vec!["3904918000".to_string()], 0.5)));
+        assert_eq!(assigned("Summit", "CUY FALLS 7-C"), Some((vec!["3915319778".to_string()], 0.5)));
+        assert_eq!(assigned("Delaware", "WESTERVILLE CITY A"), Some((vec!["3904983342".to_string()], 0.5)));
+    }
+
+    #[test]
+    fn changes_list_renamed_new_and_removed_precincts() {
+        let prior = PriorAssignments {
+            precincts: [
+                ("AKRON 1-A", "3915301000"),
+                ("AKRON 1-B", "3915301000"),
+                ("COPLEY TWP C", "3915318014"),
+            ].into_iter().map(|(name, fips)| (("Summit".to_string(), name.to_string()), vec![fips.to_string()])).collect(),
+        };
+        let current: Vec<(String, String)> = ["AKRON 1-A", "AKRON 01-B", "FAIRLAWN 3"].into_iter().map(|name| ("Summit".to_string(), name.to_string())).collect();
+
+        let changes = prior.changes(&current);
+        let changes: Vec<(&str, &str, Option<&str>, &[String])> = changes.iter()
+            .map(|change| (change.status, change.precinct.as_str(), change.previous.as_deref(), change.fips.as_slice()))
+            .collect();
+        assert_eq!(changes, [
+            ("renamed", "AKRON 01-B", Some("AKRON 1-B"), &["3915301000".to_string()][..]),
+            ("new", "FAIRLAWN 3", None, &[]),
+            ("removed", "COPLEY TWP C", None, &["3915318014".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn unknown_counties_and_names_are_not_assigned() {
+        assert_eq!(assigned("Summit", "PRECINCT 0035 WASHINGTON"), None);
+        assert_eq!(assigned("Nowhere", "AKRON 1-A"), None);
+    }
+}
@@ -1,3 +1,6 @@
+use crate::counties;
+use crate::crosswalk;
+use crate::workspace::Workspace;
 use crate::Log;
 use std::rc::Rc;
 use std::cell::RefCell;
@@ -44,18 +47,60 @@ impl PartialEq for Precinct {
 
 impl Eq for Precinct {}
 
-pub fn run(election_path: String, name: &Option<String>) {
-    use std::collections::{HashMap, HashSet};
+pub struct Options {
+    pub replace: bool,
+    pub election_id: Option<i64>,
+    pub check: bool, // only report problems; the database is only read
+    pub report: Option<std::path::PathBuf>,
+    pub keep_write_ins: bool,
+    pub name: Option<String>, // each of these is derived from cell A1 of the results if not given
+    pub date: Option<chrono::NaiveDate>,
+    pub kind: Option<ElectionKind>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ElectionKind {
+    General,
+    Primary,
+    Special,
+}
+
+impl ElectionKind {
+    /// Reads the kind from an election title such as `General Election Official Results`.
+    pub fn detect(title: &str) -> ElectionKind {
+        let title = title.to_lowercase();
+        if title.contains("primary") {
+            ElectionKind::Primary
+        } else if title.contains("special") {
+            ElectionKind::Special
+        } else {
+            ElectionKind::General
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ElectionKind::General => "general",
+            ElectionKind::Primary => "primary",
+            ElectionKind::Special => "special",
+        }
+    }
+}
+
+pub fn run(workspace: &Workspace, election_path: String, options: &Options) {
+    use std::collections::{BTreeSet, HashMap, HashSet};
     use rusqlite::Connection;
     use std::path::PathBuf;
     use colored::Colorize;
     use chrono::Datelike;
     use crate::emit;
+    use crate::diagnostics::Diagnostics;
+    use crate::layout::Layout;
     use std::fs::File;
     use std::io::Write;
-    use calamine::{Xlsx, Reader};
+    use calamine::Reader;
 
-    let workbook_uri: PathBuf = election_path.clone().into();
+    let workbook_uri = workspace.path(&election_path);
     let precinct_wb = workbook_uri.join("precinct-conversions.xlsx"); // precinct to city/township FIPS; county abbreviation to county names
     let municipal_wb = workbook_uri.join("municipal-codes.xlsx"); // fips codes to municipal names (and canonical county)
     let results_wbs = find_matching_files(&workbook_uri, "election-results");
@@ -67,46 +112,58 @@ pub fn run(election_path: String, name: &Option<String>) {
         return emit(Log::Error(format!("File does not exist: {}", municipal_wb.display().to_string().underline())));
     }
 
-    if results_wbs.len() == 0 {
+    if results_wbs.is_empty() {
         return emit(Log::Error(format!("No result workbooks found in {}", workbook_uri.display().to_string().underline())));
     }
 
-    // first we need to identify which municipalities to exclude from the final map
-    // as well as setup our tables
-    let mut filter_file = match File::create("map-filter.temp") {
-        Ok(file) => file,
-        Err(why) => return emit(Log::Error(format!("unable to open {}: {}", "map-filter.temp".underline(), why.to_string().underline())))
-    };
-
-    // these municiplaities will be merged together
-    let mut merge_file = match File::create("map-merge.temp") {
-        Ok(file) => file,
-        Err(why) => return emit(Log::Error(format!("unable to open {}: {}", "map-merge.temp".underline(), why.to_string().underline())))
-    };
-
-    if !PathBuf::from("elections.db").exists() {
-        emit(Log::Error(format!("file does not exist: {}", "elections.db".underline())));
+    if !workspace.db.exists() {
+        emit(Log::Error(format!("file does not exist: {}", workspace.db.display().to_string().underline())));
         return emit(Log::Info(format!("run the {} module", "init".underline())));
     }
 
-    let conn = match Connection::open("elections.db") {
+    // a check opens the database read-only, so it neither writes nor holds a write lock
+    let flags = if options.check { rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY } else { rusqlite::OpenFlags::default() };
+    let conn = match Connection::open_with_flags(&workspace.db, flags) {
         Ok(conn) => conn,
         Err(why) => return emit(Log::Error(format!("unable to establish connection: {}", why.to_string().underline())))
     };
 
-    let mut conn = match Connection::open("elections.db") {
-        Ok(conn) => conn,
-        Err(why) => return emit(Log::Error(format!("unable to establish connection: {}", why.to_string().underline())))
+    if !crate::schema::is_current(&conn) {
+        return;
+    }
+
+    // trades durability of the import in progress for speed; the transaction still rolls back every error.
+    // foreign keys are only enforced when enabled outside a transaction, so it is done here as well
+    if let Err(why) = conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA synchronous = OFF; PRAGMA temp_store = MEMORY; PRAGMA cache_size = -65536;") {
+        return emit(Log::Error(format!("unable to configure connection: {}", why.to_string().underline())));
+    }
+
+    let transaction = if options.check {
+        emit(Log::Info("Checking only: the database is read and nothing is written."));
+        None
+    } else {
+        emit(Log::Info("If any error occurs the database will be rolled back to this point and no further action is necessary."));
+        Some(conn.unchecked_transaction().unwrap())
     };
+    let writer = Writer::new(&conn, options.check);
 
-    let mut conn = conn.savepoint().unwrap();
-    emit(Log::Info("If any error occurs the database will be rolled back to this point and no further action is necessary."));
+    // problems are collected rather than returned so one run reports all of them
+    let mut diagnostics = Diagnostics::new();
 
     let mut precinct_wb = calamine::open_workbook_auto(precinct_wb).unwrap();
-    let mut county_wb = precinct_wb.worksheet_range("counties").unwrap();
-    let mut precinct_wb = precinct_wb.worksheet_range("precincts").unwrap();
-    let mut municipal_wb = calamine::open_workbook_auto(municipal_wb).unwrap().worksheet_range("Sheet1").unwrap();
-    let mut results_wbs: Vec<_> = results_wbs.iter().map(|wb| {
+    let county_wb = precinct_wb.worksheet_range("counties").unwrap();
+    let (previous_names, unconfirmed) = precinct_wb.worksheet_range("changes").map(|changes| crosswalk::read_previous_names(&changes)).unwrap_or_default();
+    if unconfirmed > 0 {
+        emit(Log::Warning(format!("{} suggested renames in {} are not linked to the previous election; change their status to {} once checked", unconfirmed, "precinct-conversions#changes".underline(), "confirmed".underline())));
+    }
+    let precinct_wb = precinct_wb.worksheet_range("precincts").unwrap();
+    let municipal_wb = calamine::open_workbook_auto(municipal_wb).unwrap().worksheet_range("Sheet1").unwrap();
+    let layout = match Layout::load(&workbook_uri) {
+        Ok(layout) => layout,
+        Err(why) => return emit(Log::Error(why))
+    };
+
+    let results_wbs: Vec<_> = results_wbs.iter().flat_map(|wb| {
         print!("Opening workbook {}", wb.display().to_string().underline());
         std::io::stdout().flush().expect("Unable to flush stdout.");
         let mut wb = calamine::open_workbook_auto(wb).unwrap();
@@ -115,7 +172,7 @@ pub fn run(election_path: String, name: &Option<String>) {
         for x in wb.sheet_names() {
             print!("Loading sheet {}", x.underline());
             std::io::stdout().flush().expect("Unable to flush stdout.");
-            if let "Contents" | "Master" = x.as_str() {
+            if layout.skips(&x) {
                 println!(" {}", "skipped".yellow());
                 continue; // we pass over this one because all its data is kept in the other sheets
             }
@@ -126,43 +183,93 @@ pub fn run(election_path: String, name: &Option<String>) {
         }
 
         sheets
-    }).flatten().collect();
+    }).collect();
 
-    let contents = results_wbs[0].1.get_value((0, 0)).expect("Cell A1 must at least begin with the date of the election.").to_string();
-    let (date, name) = match extract_date_and_remainder(contents.as_str()) {
-        Ok((date, title)) => (date, title.split("Official").collect::<Vec<_>>()[0].trim()),
+    let contents = results_wbs[0].1.get_value((0, 0)).map(|x| x.to_string()).unwrap_or_default();
+    let (derived_date, title) = match extract_date_and_remainder(contents.as_str()) {
+        Ok((date, title)) => (Some(date), title.split("Official").next().unwrap_or_default().trim()),
         Err(why) => {
-            emit(Log::Error(format!("Failed to get date from cell AI: {}", why.to_string())));
-            return;
+            if options.date.is_none() {
+                diagnostics.error(format!("election-results#{}", results_wbs[0].0), Some(0), Some(0), format!("Failed to get date from cell A1: {}; give it with --date", why));
+            }
+            (None, contents.trim())
         }
     };
 
-    let name = format!("{} {}", date.year(), name);
+    let date = options.date.or(derived_date).unwrap_or_default();
+    let name = match &options.name {
+        Some(name) => name.clone(),
+        None => format!("{} {}", date.year(), title)
+    };
+    let kind = options.kind.unwrap_or_else(|| ElectionKind::detect(title));
 
-    println!("{} Adding {} to the election index (date detected as {}).", "Ready!".green().bold(), name.underline(), date.to_string().underline());
-    emit(Log::Info("If this was not the desired name, delete it from the database and run again with the --name argument set."));
     let map_path: PathBuf = PathBuf::from(election_path).join("map");
-    conn.execute("INSERT INTO election_info(name, date, map) VALUES(?1, ?2, ?3);", (name.clone(), date, map_path.display().to_string())).unwrap();
-    let election_id = conn.last_insert_rowid();
+    let existing_id: Option<i64> = if !options.replace {
+        None
+    } else if let Some(id) = options.election_id {
+        match conn.query_row("SELECT id FROM election_info WHERE id=?1", [id], |row| row.get(0)) {
+            Ok(id) => Some(id),
+            Err(rusqlite::Error::QueryReturnedNoRows) => return emit(Log::Error(format!("No election exists with id={}", id))),
+            Err(why) => return emit(Log::Error(format!("Failed to look up election: {}", why.to_string().underline())))
+        }
+    } else {
+        match conn.query_row("SELECT id FROM election_info WHERE name=?1 AND date=?2 ORDER BY id LIMIT 1", (name.clone(), date), |row| row.get(0)) {
+            Ok(id) => Some(id),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                emit(Log::Info(format!("No election named {} on {} exists, so nothing will be replaced.", name.underline(), date.to_string().underline())));
+                None
+            },
+            Err(why) => return emit(Log::Error(format!("Failed to look up election: {}", why.to_string().underline())))
+        }
+    };
+
+    // precincts are relinked from wherever the election falls among the others, before or after the
+    // import; elections without a date sort first, so all are relinked after one of them
+    let mut relink_from = Some(date.to_string());
+    let election_id = match existing_id {
+        Some(id) => {
+            let previous_date: Option<String> = conn.query_row("SELECT date FROM election_info WHERE id=?1", [id], |row| row.get(0)).unwrap();
+            relink_from = relink_from.zip(previous_date).map(|(date, previous_date)| date.min(previous_date));
+            println!("{} Replacing election id={} with {} ({} election on {}).", "Ready!".green().bold(), id, name.underline(), kind.as_str(), date.to_string().underline());
+            if let Err(why) = writer.run(|conn| delete_election_data(conn, id)) {
+                return emit(Log::Error(format!("Failed to remove the existing election: {}", why.to_string().underline())));
+            }
+
+            writer.execute("UPDATE election_info SET name=?1, date=?2, map=?3, kind=?4 WHERE id=?5", (name.clone(), date, map_path.display().to_string(), kind.as_str(), id));
+            id
+        },
+
+        None => {
+            println!("{} Adding {} to the election index ({} election on {}).", "Ready!".green().bold(), name.underline(), kind.as_str(), date.to_string().underline());
+            emit(Log::Info("If these were not the desired name, kind or date, run again with --name, --kind or --date and --replace set."));
+            writer.insert("INSERT INTO election_info(name, date, map, kind) VALUES(?1, ?2, ?3, ?4);", (name.clone(), date, map_path.display().to_string(), kind.as_str()))
+        }
+    };
 
     let mut county_abbr_lookup: HashMap<String, String> = HashMap::new(); // abbr -> name
     let mut county_lookup: HashMap<String, Rc<County>> = HashMap::new();
     for row in 0..county_wb.get_size().0 {
         let row = row as u32;
 
-        let (abbr, name) = match (county_wb.get_value((row, 0)), county_wb.get_value((row, 1))) {
-            (Some(abbr), Some(name))=> (abbr.to_string(), name.to_string()),
-            _ => return emit(Log::Error(format!("Missing county abbreviation or name in precinct-conversions#counties row={}", row)))
-        };
-
-        conn.execute("INSERT INTO county(name, electionId) VALUES(?1, ?2)", (name.clone(), election_id)).unwrap();
-        let county = County {
-            name: name.clone(),
-            id: conn.last_insert_rowid()
+        let abbr = county_wb.get_value((row, 0)).map(|abbr| abbr.to_string()).unwrap_or_default();
+        let name = county_wb.get_value((row, 1)).map(|name| name.to_string()).unwrap_or_default();
+        let name = match (abbr.is_empty(), name.is_empty()) {
+            (false, false) => name,
+            (false, true) => match counties::by_code(&abbr) { // the name may be left out of Ohio's own codes
+                Some(county) => county.name.to_string(),
+                None => {
+                    diagnostics.error("precinct-conversions#counties", Some(row), Some(1), format!("Missing the name of county {}", abbr));
+                    continue;
+                }
+            },
+            _ => {
+                diagnostics.error("precinct-conversions#counties", Some(row), None, "Missing county abbreviation or name");
+                continue;
+            }
         };
 
-        county_abbr_lookup.insert(abbr, name.clone());
-        county_lookup.insert(name, Rc::new(county));
+        county_lookup.insert(name.clone(), Rc::new(insert_county(&writer, &name, election_id)));
+        county_abbr_lookup.insert(abbr, name);
     }
 
     let mut municipal_lookup: HashMap<String, Rc<Municipality>> = HashMap::new(); // fips -> municipality
@@ -171,25 +278,48 @@ pub fn run(election_path: String, name: &Option<String>) {
 
         let (name, county_abbr, r#type, fips) = match (municipal_wb.get_value((row, 0)), municipal_wb.get_value((row, 2)), municipal_wb.get_value((row, 1)), municipal_wb.get_value((row, 3))) {
             (Some(name), Some(county), Some(r#type), Some(fips)) => (name.to_string(), county.to_string(), r#type.to_string(), fips.to_string()),
-            _ => return emit(Log::Error(format!("Missing name, county abbreviation, type, or FIPS code in municipal-codes row={}", row)))
+            _ => {
+                diagnostics.error("municipal-codes", Some(row), None, "Missing name, county abbreviation, type, or FIPS code");
+                continue;
+            }
         };
 
+        // counties missing from precinct-conversions#counties are looked up among Ohio's own
+        if !county_abbr_lookup.contains_key(&county_abbr) {
+            if let Some(county) = counties::by_code(&county_abbr) {
+                let name = county.name.to_string();
+                county_lookup.entry(name.clone()).or_insert_with(|| Rc::new(insert_county(&writer, &name, election_id)));
+                county_abbr_lookup.insert(county_abbr.clone(), name);
+            }
+        }
+
         let canonical_county = match county_abbr_lookup.get(&county_abbr) {
             Some(county_name) => match county_lookup.get(&county_name.clone()) {
                 Some(county) => Rc::clone(county),
-                None => return emit(Log::Error(format!("Could not resolve county by name of {} on municipal-codes row={}", county_name.underline(), row)))
+                None => {
+                    diagnostics.error("municipal-codes", Some(row), Some(2), format!("Could not resolve county by name of {}", county_name));
+                    continue;
+                }
             },
 
-            None => return emit(Log::Error(format!("Could not find county by abbreviation {} on municipal-codes row={}", county_abbr.underline(), row)))
+            None => {
+                diagnostics.error("municipal-codes", Some(row), Some(2), format!("Could not find county by abbreviation {}", county_abbr));
+                continue;
+            }
+        };
+
+        let r#type = match r#type.as_str() {
+            "city/village" => MunicipalType::City,
+            "township" => MunicipalType::Township,
+            _ => {
+                diagnostics.error("municipal-codes", Some(row), Some(1), format!("Unknown municipal type {}", r#type));
+                continue;
+            }
         };
 
         municipal_lookup.insert(fips.clone(), Rc::new(Municipality {
             name,
-            r#type: match r#type.as_str() {
-                "city/village" => MunicipalType::City,
-                "township" => MunicipalType::Township,
-                _ => return emit(Log::Error(format!("Unknown municipal type {} in municipal-codes row={}", r#type.underline(), row)))
-            },
+            r#type,
             fips,
             canonical_county,
             precincts: Rc::new(RefCell::new(Vec::new())),
@@ -197,20 +327,28 @@ pub fn run(election_path: String, name: &Option<String>) {
         }));
     }
 
-    for row in 0..precinct_wb.get_size().0 {
+    let mut unassigned: HashSet<(String, String)> = HashSet::new(); // (county name, precinct name) already reported
+    'precincts: for row in 0..precinct_wb.get_size().0 {
         let row = row as u32;
 
         let (county, name) = match (precinct_wb.get_value((row, 0)), precinct_wb.get_value((row, 1))) {
             (Some(county), Some(name)) => {
                 let county = match county_lookup.get(&county.to_string()) {
                     Some(county) => Rc::clone(county),
-                    None => return emit(Log::Error(format!("Unable to find county {} on precinct-conversions#precincts row={}", county.to_string().underline(), row)))
+                    None => {
+                        diagnostics.error("precinct-conversions#precincts", Some(row), Some(0), format!("Unable to find county {}", county));
+                        unassigned.insert((county.to_string(), name.to_string()));
+                        continue;
+                    }
                 };
 
                 (county, name.to_string())
             },
 
-            _ => return emit(Log::Error(format!("Missing county or name in precinct-conversions#precincts row={}", row)))
+            _ => {
+                diagnostics.error("precinct-conversions#precincts", Some(row), None, "Missing county or name");
+                continue;
+            }
         };
 
         let precinct = Precinct {
@@ -222,18 +360,22 @@ pub fn run(election_path: String, name: &Option<String>) {
             let col = col as u32;
 
             let fips = precinct_wb.get_value((row, col)).unwrap().to_string();
-            if fips.is_empty() {
-                break
+            if fips.is_empty() || !fips.chars().all(|c| c.is_ascii_digit()) {
+                break // the codes may be followed by the confidence written by extract-municipal-codes
             } else {
                 fips_codes.push(fips);
             }
         }
 
         let mut municis = Vec::new();
-        for fips in &fips_codes {
+        for (idx, fips) in fips_codes.iter().enumerate() {
             municis.push(match municipal_lookup.get(fips) {
                 Some(boxed) => Rc::clone(boxed),
-                None => return emit(Log::Error(format!("Unable to find municipality from FIPS code {} on precinct-conversions#precincts row={}", fips.underline(), row)))
+                None => {
+                    diagnostics.error("precinct-conversions#precincts", Some(row), Some(2 + idx as u32), format!("Unable to find municipality from FIPS code {}", fips));
+                    unassigned.insert((county.name.clone(), name));
+                    continue 'precincts;
+                }
             });
         }
 
@@ -245,22 +387,18 @@ pub fn run(election_path: String, name: &Option<String>) {
                     municis_names.push(muni.name.clone());
                 }
             });*/
-            let mut idx = 1;
-            for _ in idx..fips_codes.len() {
-                let muni = &municis[idx];
+            for muni in municis.iter().skip(1) {
                 if fips_codes.contains(&muni.fips) {
                     municis_names.push(muni.name.clone());
                 }
-
-                idx = idx + 1;
             }
 
             let mut precincts: HashSet<Rc<Precinct>> = HashSet::new();
-            let mut merges: HashSet<String> = HashSet::new();
+            let mut merges: BTreeSet<String> = BTreeSet::new(); // ordered so the merged FIPS string matches the one finalize-map writes
 
             municis.iter().for_each(|muni| {
                 for p in &*muni.precincts.borrow() {
-                    precincts.insert(Rc::clone(&p));
+                    precincts.insert(Rc::clone(p));
                 }
 
                 //merges.insert(muni.fips.clone());
@@ -269,13 +407,14 @@ pub fn run(election_path: String, name: &Option<String>) {
                 }
             });
 
+            let merges: Vec<String> = merges.into_iter().collect();
             let new_munc = Rc::new(Municipality {
                 name: municis_names.join(" / "),
-                fips: fips_codes.join(","),
+                fips: merges.join(","),
                 r#type: MunicipalType::Mixed,
                 canonical_county: Rc::clone(&municis[0].canonical_county),
                 precincts: Rc::new(RefCell::new(precincts.into_iter().collect())),
-                merges: merges.into_iter().collect(),
+                merges,
             });
 
             for fips in &new_munc.merges {
@@ -290,31 +429,39 @@ pub fn run(election_path: String, name: &Option<String>) {
             }
         }
 
-        if fips_codes.len() == 0 {
-            return emit(Log::Error(format!("Precinct {} in {} County not assigned to municipality on precinct-conversions#precincts row={}", name, county.name, row)));
+        if fips_codes.is_empty() {
+            diagnostics.error("precinct-conversions#precincts", Some(row), Some(2), format!("Precinct {} in {} County not assigned to municipality", name, county.name));
+            unassigned.insert((county.name.clone(), name));
+            continue;
         }
 
         let munc = match municipal_lookup.get(&fips_codes[0]) { // at this point this is the only municipality present or all the fips codes now point to the same merged entity
             Some(munc) => Rc::clone(munc),
-            None => return emit(Log::Error(format!("Unable to find municipality with FIPS code {} on precinct-conversions#precincts row={}", fips_codes[0].underline(), row)))
+            None => {
+                diagnostics.error("precinct-conversions#precincts", Some(row), Some(2), format!("Unable to find municipality with FIPS code {}", fips_codes[0]));
+                unassigned.insert((county.name.clone(), name));
+                continue;
+            }
         };
 
         munc.precincts.borrow_mut().push(Rc::new(precinct));
     }
 
     let mut muncs_fips: HashSet<String> = HashSet::new();
+    let mut filtered: Vec<String> = Vec::new(); // municipalities to exclude from the final map
+    let mut merged: Vec<String> = Vec::new(); // municipalities to be merged together on the final map
 
     for (_, muni) in municipal_lookup.iter() {
-        if muni.precincts.borrow().len() == 0 {
+        if muni.precincts.borrow().is_empty() {
             if let MunicipalType::Township = muni.r#type {
                 emit(Log::Warning(format!("{} (township canonically in {} County) was assigned 0 precincts.", muni.name, muni.canonical_county.name)));
             }
 
-            write!(filter_file, "{}\n", muni.fips).unwrap();
+            filtered.push(muni.fips.clone());
             continue;
         }
 
-        if muni.merges.len() > 0 { write!(merge_file, "{}={}\n", muni.merges.join(","), muni.fips.split(",").collect::<Vec<_>>()[0]).unwrap(); }
+        if !muni.merges.is_empty() { merged.push(format!("{}={}", muni.merges.join(","), muni.fips.split(",").collect::<Vec<_>>()[0])); }
         muncs_fips.insert(muni.fips.clone());
     }
 
@@ -323,118 +470,517 @@ pub fn run(election_path: String, name: &Option<String>) {
     }).collect::<Vec<_>>();
 
     let muncs = muncs_fips.iter().map(|fips| {
-        Rc::clone(&municipal_lookup.get(&fips.to_string()).unwrap())
+        Rc::clone(municipal_lookup.get(*fips).unwrap())
     }).collect::<Vec<_>>();
 
+    // every results sheet repeats the registered voters and ballots cast, so the first one seen is kept
+    let mut precinct_stats: HashMap<(String, String), PrecinctStats> = HashMap::new();
+    for (name, sheet) in results_wbs.iter() {
+        let party_columns = if matches!(kind, ElectionKind::Primary) { party_ballots_columns(&layout, sheet) } else { Vec::new() };
+        for row in layout.first_data_row..sheet.get_size().0 as u32 {
+            let county_name = sheet.get_value((row, layout.county_column)).map(|x| x.to_string()).unwrap_or_default();
+            let precinct_name = sheet.get_value((row, layout.precinct_column)).map(|x| x.to_string()).unwrap_or_default();
+            if precinct_stats.contains_key(&(county_name.clone(), precinct_name.clone())) {
+                continue;
+            }
+
+            let mut stats = [None, None];
+            for (stat, col) in stats.iter_mut().zip([layout.registered_voters_column, layout.ballots_cast_column]) {
+                match read_count(sheet.get_value((row, col))) {
+                    Ok(count) => *stat = count,
+                    Err(why) => diagnostics.error(format!("election-results#{}", name), Some(row), Some(col), why)
+                }
+            }
+
+            let mut party_ballots = Vec::new();
+            for (party, col) in party_columns.iter() {
+                match read_count(sheet.get_value((row, *col))) {
+                    Ok(Some(count)) => party_ballots.push((*party, count)),
+                    Ok(None) => {},
+                    Err(why) => diagnostics.error(format!("election-results#{}", name), Some(row), Some(*col), why)
+                }
+            }
+
+            precinct_stats.insert((county_name, precinct_name), PrecinctStats { registered_voters: stats[0], ballots_cast: stats[1], party_ballots });
+        }
+    }
+
     let mut precinct_lookup: HashMap<(String, String), (Rc<Precinct>, i64)> = HashMap::new(); // (county name, precinct name) -> (Precinct, row_id)
 
     print!("Importing municipalities and precincts into database");
     std::io::stdout().flush().expect("Unable to flush stdout.");
     for muni in muncs.iter() {
-        conn.execute("INSERT INTO municipality(name, fips, electionId) VALUES(?1, ?2, ?3)", (muni.name.clone(), muni.fips.clone(), election_id)).unwrap();
-        let muni_id = conn.last_insert_rowid();
+        let muni_id = writer.insert("INSERT INTO municipality(name, fips, electionId) VALUES(?1, ?2, ?3)", (muni.name.clone(), muni.fips.clone(), election_id));
+        writer.run(|conn| crosswalk::save_municipal_codes(conn, muni_id, &muni.fips)).unwrap();
         for p in &*muni.precincts.borrow() {
-            conn.execute("INSERT INTO precinct(name, municipalId, countyId) VALUES(?1, ?2, ?3)", (p.name.clone(), muni_id, p.county.id)).unwrap();
-            precinct_lookup.insert((p.county.name.clone(), p.name.clone()), (Rc::clone(&p), conn.last_insert_rowid()));
+            let stats = precinct_stats.remove(&(p.county.name.clone(), p.name.clone())).unwrap_or_default();
+            let precinct_id = writer.insert("INSERT INTO precinct(name, municipalId, countyId, registeredVoters, ballotsCast) VALUES(?1, ?2, ?3, ?4, ?5)", (p.name.clone(), muni_id, p.county.id, stats.registered_voters, stats.ballots_cast));
+            for (party, ballots) in stats.party_ballots {
+                writer.execute("INSERT INTO precinct_party_ballots(precinctId, party, ballotsCast) VALUES(?1, ?2, ?3)", (precinct_id, party, ballots));
+            }
+            precinct_lookup.insert((p.county.name.clone(), p.name.clone()), (Rc::clone(p), precinct_id));
         }
     }
 
+    let precinct_ids = precinct_lookup.iter().map(|(key, (_, id))| (key.clone(), *id)).collect();
+    writer.run(|conn| crosswalk::save_previous_names(conn, &precinct_ids, &previous_names)).unwrap();
     println!(" {}", "done".green());
 
-    for (idx, (name, sheet)) in results_wbs.iter().enumerate() {
+    let started = std::time::Instant::now();
+    let mut results = ResultBatch::new(&writer);
+    let mut office_names: Vec<String> = Vec::new();
+    for (name, sheet) in results_wbs.iter() {
         print!("Importing precinct results for {} elections", name.underline());
         std::io::stdout().flush().expect("Unable to flush stdout.");
 
-        conn.execute("INSERT INTO office_category(name, electionId) VALUES(?1, ?2)", (name, election_id)).unwrap();
-        let category_id = conn.last_insert_rowid();
+        // primary workbooks split the contests by party ballot, named in the sheet or else in each office
+        let primary = matches!(kind, ElectionKind::Primary);
+        let category_party = detect_ballot_party(name).filter(|_| primary);
+        let category_id = writer.insert("INSERT INTO office_category(name, ballot_party, electionId) VALUES(?1, ?2, ?3)", (name, category_party, election_id));
+
+        // each row is looked up once rather than once per candidate
+        let precinct_ids: Vec<Option<i64>> = (layout.first_data_row..sheet.get_size().0 as u32).map(|row| {
+            let county_name = sheet.get_value((row, layout.county_column)).map(|x| x.to_string()).unwrap_or_default();
+            let precinct_name = sheet.get_value((row, layout.precinct_column)).map(|x| x.to_string()).unwrap_or_default();
+            let key = (county_name, precinct_name);
+            if let Some((_, precinct_id)) = precinct_lookup.get(&key) {
+                return Some(*precinct_id);
+            }
+
+            // precincts that failed in precinct-conversions were already reported there
+            let blank = key.0.is_empty() && key.1.is_empty();
+            if !blank && !unassigned.contains(&key) {
+                diagnostics.error(format!("election-results#{}", name), Some(row), Some(layout.precinct_column), format!("Unable to find precinct {} in {} county", key.1, key.0));
+            }
+            None
+        }).collect();
 
-        let mut office_name = String::new();
-        let mut office_id: i64 = -1;
-        for col in 8..sheet.get_size().1 {
+        let mut office_id: Option<i64> = None;
+        let mut offices: Vec<(i64, String, Vec<String>)> = Vec::new(); // (id, name, candidates other than write-ins)
+        for col in layout.first_candidate_column as usize..sheet.get_size().1 {
             let col = col as u32;
 
-            if let Some(name) = sheet.get_value((0, col)) {
-                office_name = name.to_string().trim().to_string();
+            if let Some(name) = sheet.get_value((layout.office_row, col)) {
+                let office_name = name.to_string().trim().to_string();
                 if !office_name.is_empty() {
-                    conn.execute("INSERT INTO office_election(name, categoryId) VALUES(?1, ?2)", (office_name.clone(), category_id)).unwrap();
-                    office_id = conn.last_insert_rowid();
+                    let ballot_party = category_party.or_else(|| detect_ballot_party(&office_name).filter(|_| primary));
+                    let id = writer.insert("INSERT INTO office_election(name, seats, ballot_party, categoryId) VALUES(?1, ?2, ?3, ?4)", (office_name.clone(), parse_seats(&office_name), ballot_party, category_id));
+                    office_id = Some(id);
+                    offices.push((id, office_name, Vec::new()));
+                }
+            }
+
+            let candidate_name = sheet.get_value((layout.candidate_row, col)).unwrap().to_string();
+            let Some(office_id) = office_id else {
+                diagnostics.error(format!("election-results#{}", name), Some(layout.office_row), Some(col), format!("Candidate {} comes before the name of any office", candidate_name.trim()));
+                continue;
+            };
+            let (candidate_name, party, write_in) = match candidate_name.trim().strip_suffix("(WI)*") {
+                Some(_) if !options.keep_write_ins => continue,
+                Some(name) => (name.trim_end().to_string(), None, true),
+                None => {
+                    let (name, party) = split_party(&candidate_name);
+                    (name, party, false)
                 }
+            };
+            let candidate_id = writer.insert("INSERT INTO candidate(name, party, is_write_in, officeId) VALUES(?1, ?2, ?3, ?4)", (candidate_name.clone(), party, write_in, office_id));
+            if let Some((_, _, candidates)) = offices.last_mut().filter(|_| !write_in) {
+                candidates.push(candidate_name);
             }
 
-            let candidate_name = sheet.get_value((1, col)).unwrap().to_string();
-            if candidate_name.ends_with("(WI)*") { continue; }
-            conn.execute("INSERT INTO candidate(name, officeId) VALUES(?1, ?2)", (candidate_name.clone(), office_id)).unwrap();
-            let candidate_id = conn.last_insert_rowid();
-
-            for row in 4..sheet.get_size().0 {
-                let row = row as u32;
-                let county_name = sheet.get_value((row, 0)).unwrap().to_string();
-                let precinct_name = sheet.get_value((row, 1)).unwrap().to_string();
-                let votes = sheet.get_value((row, col)).unwrap().to_string();
-                if votes == "0" { continue; }
-                let (precinct, precinct_id) = match precinct_lookup.get(&(county_name.clone(), precinct_name.clone())) {
-                    Some((precinct, precinct_id)) => (Rc::clone(&precinct), precinct_id),
-                    None => return emit(Log::Error(format!("Unable to find precinct {} in {} county on election-results#{} row={}", precinct_name.underline(), county_name.underline(), name.underline(), row)))
+            for (row, precinct_id) in (layout.first_data_row..).zip(precinct_ids.iter()) {
+                let Some(precinct_id) = precinct_id else { continue };
+                let votes = match read_count(sheet.get_value((row, col))) {
+                    Ok(Some(votes)) => votes,
+                    Ok(None) => continue, // the office was not on this precinct's ballot; zeroes are kept
+                    Err(why) => {
+                        diagnostics.error(format!("election-results#{}", name), Some(row), Some(col), why);
+                        continue;
+                    }
                 };
 
-                conn.execute("INSERT INTO result(votes, candidateId, precinctId) VALUES(?1, ?2, ?3)", (votes, candidate_id, precinct_id)).unwrap();
+                results.push(votes, candidate_id, *precinct_id).unwrap();
+            }
+        }
+        results.flush().unwrap();
+
+        for (office_id, office_name, candidates) in offices {
+            office_names.push(office_name.clone());
+            if let Some(threshold) = layout.issue_threshold(&office_name).or_else(|| is_issue(&candidates).then_some(0.5)) {
+                writer.execute("UPDATE office_election SET kind='issue', threshold=?1, ballot_party=NULL WHERE id=?2", (threshold, office_id)); // issues are on every ballot
             }
         }
 
         println!(" {}", "done".green());
     }
 
-    println!("Creating index tables");
-    conn.execute("DROP TABLE IF EXISTS indexed_precinct_results", ()).unwrap();
-    conn.execute("DROP TABLE IF EXISTS indexed_county_results", ()).unwrap();
-    conn.execute("DROP TABLE IF EXISTS indexed_municipal_results", ()).unwrap();
-    conn.execute("DROP TABLE IF EXISTS indexed_state_results", ()).unwrap();
+    let elapsed = started.elapsed().as_secs_f64();
+    emit(Log::Info(format!("{} {} results in {:.1}s ({:.0} rows/s)", if options.check { "Checked" } else { "Imported" }, results.inserted, elapsed, results.inserted as f64 / elapsed.max(f64::EPSILON))));
+    drop(results);
+
+    // votes are bound as integers above, but the router decodes them as such and anything else breaks it;
+    // a check stores nothing, and read_count only passes whole non-negative counts
+    let untyped: i64 = if options.check { 0 } else { conn.query_row("
+        SELECT count(*) FROM result r JOIN precinct p ON r.precinctId = p.id JOIN county c ON p.countyId = c.id
+        WHERE c.electionId = ?1 AND (typeof(r.votes) != 'integer' OR r.votes < 0)
+    ", [election_id], |row| row.get(0)).unwrap() };
+    if untyped > 0 {
+        diagnostics.error("result", None, None, format!("{} stored vote count(s) are not non-negative integers", untyped));
+    }
 
-    print!("\tindexed_precinct_results");
-    std::io::stdout().flush().expect("Unable to flush stdout.");
-    conn.execute("CREATE TABLE indexed_precinct_results AS SELECT * FROM precinct_results", ()).unwrap();
-    println!(" {}", "done".green());
+    for office in layout.unmatched_thresholds(&office_names) {
+        diagnostics.error("layout.toml", None, None, format!("Issue threshold for {} matches no office in the results", office));
+    }
 
-    print!("\tindexed_county_results");
-    std::io::stdout().flush().expect("Unable to flush stdout.");
-    conn.execute("CREATE TABLE indexed_county_results AS SELECT * FROM county_results", ()).unwrap();
-    println!(" {}", "done".green());
+    if let Some(report) = &options.report {
+        match diagnostics.save(report) {
+            Ok(_) => emit(Log::Info(format!("Wrote {} problem(s) to {}", diagnostics.len(), report.display().to_string().underline()))),
+            Err(why) => emit(Log::Error(why))
+        }
+    }
 
-    print!("\tindexed_municipal_results");
-    std::io::stdout().flush().expect("Unable to flush stdout.");
-    conn.execute("CREATE TABLE indexed_municipal_results AS SELECT * FROM municipal_results", ()).unwrap();
-    println!(" {}", "done".green());
+    if !diagnostics.is_empty() {
+        diagnostics.print();
+        return emit(Log::Error(format!("Found {} problem(s); {}", diagnostics.len(), if options.check { "fix them before importing" } else { "the database was rolled back" })));
+    }
 
-    print!("\tindexed_state_results");
-    std::io::stdout().flush().expect("Unable to flush stdout.");
-    conn.execute("CREATE TABLE indexed_state_results AS SELECT * FROM state_results", ()).unwrap();
-    println!(" {}", "done".green());
+    let Some(transaction) = transaction else {
+        return println!("{} No problems found in {}.", "Finished!".green().bold(), workbook_uri.display().to_string().underline());
+    };
 
-    conn.commit().unwrap();
+    if let Err(why) = crosswalk::link_precincts(&conn, relink_from.as_deref().map(|date| (date, election_id))) {
+        return emit(Log::Error(format!("Failed to link precincts across elections: {}", why.to_string().underline())));
+    }
+
+    if let Err(why) = refresh_indexes(&conn, election_id) {
+        return emit(Log::Error(format!("Failed to refresh index tables: {}", why.to_string().underline())));
+    }
+
+    // read by finalize-map to drop the filtered municipalities and dissolve the merged ones
+    for (file, lines) in [(workspace.filter_file(), &filtered), (workspace.merge_file(), &merged)] {
+        let written = File::create(&file).and_then(|mut f| lines.iter().try_for_each(|line| writeln!(f, "{}", line)));
+        if let Err(why) = written {
+            return emit(Log::Error(format!("unable to write {}: {}", file.display().to_string().underline(), why.to_string().underline())));
+        }
+    }
+
+    transaction.commit().unwrap();
 
     println!("{} Successfully imported {} into the database.", "Finished!".green().bold(), name.underline());
 }
 
-fn extract_date_and_remainder(input: &str) -> Result<(chrono::NaiveDate, &str), chrono::ParseError> {
-    use chrono::{NaiveDate};
-    use chrono::format::{ParseError, ParseErrorKind};
+/// Inserts a county of the election, with its FIPS code if it is one of Ohio's.
+fn insert_county(writer: &Writer, name: &str, election_id: i64) -> County {
+    let fips = counties::by_name(name).map(|county| county.fips);
+    let id = writer.insert("INSERT INTO county(name, fips, electionId) VALUES(?1, ?2, ?3)", (name, fips, election_id));
+    County {
+        name: name.to_string(),
+        id
+    }
+}
 
-    let format = "%B %d, %Y";
-    
-    if let Some(comma_pos) = input.find(',') {
-        let date_str = &input[..comma_pos + 6];
+/// Writes the rows of an import. With `--check` nothing is written and each insert is only given
+/// an id of its own, so the workbooks go through the same steps without touching the database.
+struct Writer<'a> {
+    conn: &'a rusqlite::Connection,
+    check: bool,
+    last_id: std::cell::Cell<i64>,
+}
 
-        let date = NaiveDate::parse_from_str(date_str, format)?;
+impl<'a> Writer<'a> {
+    fn new(conn: &'a rusqlite::Connection, check: bool) -> Writer<'a> {
+        Writer {
+            conn,
+            check,
+            last_id: std::cell::Cell::new(0),
+        }
+    }
 
-        let remainder = &input[comma_pos + 7..];
+    /// Inserts a row and returns its id.
+    fn insert<P: rusqlite::Params>(&self, sql: &str, params: P) -> i64 {
+        if self.check {
+            self.last_id.set(self.last_id.get() + 1);
+            return self.last_id.get();
+        }
 
-        Ok((date, remainder))
-    } else {
-        match NaiveDate::parse_from_str("November 5", format) {
-            Ok(_) => panic!("This statement can never be reached."),
-            Err(why) => Err(why)
-        } // trigger ParseError::NotEnough
+        self.execute(sql, params);
+        self.conn.last_insert_rowid()
     }
+
+    fn execute<P: rusqlite::Params>(&self, sql: &str, params: P) {
+        if !self.check {
+            self.conn.prepare_cached(sql).and_then(|mut stmt| stmt.execute(params)).unwrap();
+        }
+    }
+
+    /// Runs writes made outside this module, such as those of crosswalk.
+    fn run<F: FnOnce(&rusqlite::Connection) -> rusqlite::Result<()>>(&self, write: F) -> rusqlite::Result<()> {
+        if self.check {
+            return Ok(());
+        }
+
+        write(self.conn)
+    }
+}
+
+/// Buffers rows of `result` to insert them many at a time, which is far quicker than one by one.
+struct ResultBatch<'a> {
+    writer: &'a Writer<'a>,
+    rows: Vec<i64>, // votes, candidateId and precinctId of each row in turn
+    inserted: usize,
+}
+
+impl<'a> ResultBatch<'a> {
+    // three parameters a row; SQLite before 3.32, which rusqlite may link from the system, allows 999 a statement
+    const ROWS: usize = 333;
+
+    fn new(writer: &'a Writer<'a>) -> ResultBatch<'a> {
+        ResultBatch {
+            writer,
+            rows: Vec::with_capacity(ResultBatch::ROWS * 3),
+            inserted: 0,
+        }
+    }
+
+    fn push(&mut self, votes: i64, candidate_id: i64, precinct_id: i64) -> rusqlite::Result<()> {
+        self.rows.extend([votes, candidate_id, precinct_id]);
+        if self.rows.len() >= ResultBatch::ROWS * 3 {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> rusqlite::Result<()> {
+        let count = self.rows.len() / 3;
+        if count == 0 {
+            return Ok(());
+        }
+
+        let sql = format!("INSERT INTO result(votes, candidateId, precinctId) VALUES {}", vec!["(?, ?, ?)"; count].join(", "));
+        self.writer.run(|conn| conn.prepare_cached(&sql)?.execute(rusqlite::params_from_iter(self.rows.iter())).map(|_| ()))?;
+        self.inserted += count;
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+/// The `indexed_*` tables the server reads, the views they copy, and which of their rows belong to
+/// the election `?1`. SQLite only pushes plain comparisons into the grouped views, so results are
+/// also bound to the range `?2..=?3` of the election's candidate ids; without it every election is
+/// aggregated on each import.
+const INDEXED: [(&str, &str, &str); 7] = [
+    ("indexed_precinct_results", "precinct_results", "candidateId BETWEEN ?2 AND ?3 AND candidateId IN (SELECT c.id FROM candidate c JOIN office_election o ON c.officeId = o.id JOIN office_category oc ON o.categoryId = oc.id WHERE oc.electionId = ?1)"),
+    ("indexed_county_results", "county_results", "candidateId BETWEEN ?2 AND ?3 AND candidateId IN (SELECT c.id FROM candidate c JOIN office_election o ON c.officeId = o.id JOIN office_category oc ON o.categoryId = oc.id WHERE oc.electionId = ?1)"),
+    ("indexed_municipal_results", "municipal_results", "candidateId BETWEEN ?2 AND ?3 AND candidateId IN (SELECT c.id FROM candidate c JOIN office_election o ON c.officeId = o.id JOIN office_category oc ON o.categoryId = oc.id WHERE oc.electionId = ?1)"),
+    ("indexed_state_results", "state_results", "candidateId BETWEEN ?2 AND ?3 AND candidateId IN (SELECT c.id FROM candidate c JOIN office_election o ON c.officeId = o.id JOIN office_category oc ON o.categoryId = oc.id WHERE oc.electionId = ?1)"),
+    ("indexed_county_turnout", "county_turnout", "countyId IN (SELECT id FROM county WHERE electionId = ?1)"),
+    ("indexed_municipal_turnout", "municipal_turnout", "municipalId IN (SELECT id FROM municipality WHERE electionId = ?1)"),
+    ("indexed_state_turnout", "state_turnout", "electionId = ?1"),
+];
+
+/// Refills every `indexed_*` table from its view, for every election.
+pub fn rebuild_indexes(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    use colored::Colorize;
+    use std::io::Write;
+
+    println!("Rebuilding index tables");
+    for (table, view, _) in INDEXED {
+        print!("\t{}", table);
+        std::io::stdout().flush().expect("Unable to flush stdout.");
+        conn.execute(&format!("DELETE FROM {}", table), ())?;
+        conn.execute(&format!("INSERT INTO {} SELECT * FROM {}", table, view), ())?;
+        println!(" {}", "done".green());
+    }
+
+    Ok(())
+}
+
+/// Counts, for each `indexed_*` table, the rows of its view it is missing and the rows it holds that
+/// its view no longer has.
+pub fn compare_indexes(conn: &rusqlite::Connection) -> rusqlite::Result<Vec<(&'static str, i64, i64)>> {
+    INDEXED.iter().map(|(table, view, _)| {
+        let missing = conn.query_row(&format!("SELECT count(*) FROM (SELECT * FROM {} EXCEPT SELECT * FROM {})", view, table), (), |row| row.get(0))?;
+        let stale = conn.query_row(&format!("SELECT count(*) FROM (SELECT * FROM {} EXCEPT SELECT * FROM {})", table, view), (), |row| row.get(0))?;
+        Ok((*table, missing, stale))
+    }).collect()
+}
+
+/// Refills the rows of one election in the `indexed_*` tables, leaving the other elections as they are.
+pub fn refresh_indexes(conn: &rusqlite::Connection, election_id: i64) -> rusqlite::Result<()> {
+    use colored::Colorize;
+    use std::io::Write;
+
+    println!("Refreshing index tables");
+    clear_indexes(conn, election_id)?;
+    for (table, view, filter) in INDEXED {
+        print!("\t{}", table);
+        std::io::stdout().flush().expect("Unable to flush stdout.");
+        execute_for_election(conn, &format!("INSERT INTO {} SELECT * FROM {} WHERE {}", table, view, filter), election_id)?;
+        println!(" {}", "done".green());
+    }
+
+    Ok(())
+}
+
+/// Removes the rows of one election from the `indexed_*` tables; its candidates and units must still exist.
+fn clear_indexes(conn: &rusqlite::Connection, election_id: i64) -> rusqlite::Result<()> {
+    for (table, _, filter) in INDEXED {
+        execute_for_election(conn, &format!("DELETE FROM {} WHERE {}", table, filter), election_id)?;
+    }
+
+    Ok(())
+}
+
+/// Runs a statement filtered by an `INDEXED` filter, binding as many of its parameters as it uses.
+fn execute_for_election(conn: &rusqlite::Connection, sql: &str, election_id: i64) -> rusqlite::Result<usize> {
+    let (first, last): (i64, i64) = conn.query_row("
+        SELECT coalesce(min(c.id), 0), coalesce(max(c.id), -1) FROM candidate c
+        JOIN office_election o ON c.officeId = o.id JOIN office_category oc ON o.categoryId = oc.id WHERE oc.electionId = ?1
+    ", [election_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    let mut stmt = conn.prepare(sql)?;
+    let params = [election_id, first, last];
+    let count = stmt.parameter_count();
+    stmt.execute(rusqlite::params_from_iter(&params[..count]))
+}
+
+/// Removes everything imported for an election, keeping its `election_info` row.
+pub fn delete_election_data(conn: &rusqlite::Connection, election_id: i64) -> rusqlite::Result<()> {
+    let offices = "SELECT o.id FROM office_election o JOIN office_category oc ON o.categoryId = oc.id WHERE oc.electionId = ?1";
+    let precincts = "SELECT p.id FROM precinct p JOIN county c ON p.countyId = c.id WHERE c.electionId = ?1";
+
+    clear_indexes(conn, election_id)?;
+    conn.execute(&format!("DELETE FROM result WHERE candidateId IN (SELECT id FROM candidate WHERE officeId IN ({}))", offices), [election_id])?;
+    conn.execute(&format!("DELETE FROM result WHERE precinctId IN ({})", precincts), [election_id])?;
+    conn.execute(&format!("DELETE FROM candidate WHERE officeId IN ({})", offices), [election_id])?;
+    conn.execute("DELETE FROM office_election WHERE categoryId IN (SELECT id FROM office_category WHERE electionId = ?1)", [election_id])?;
+    conn.execute("DELETE FROM office_category WHERE electionId = ?1", [election_id])?;
+    // links from later elections are dropped too; callers relink the remaining precincts afterwards
+    conn.execute(&format!("DELETE FROM precinct_crosswalk WHERE precinctId IN ({0}) OR previousId IN ({0})", precincts), [election_id])?;
+    conn.execute(&format!("DELETE FROM precinct_alias WHERE precinctId IN ({})", precincts), [election_id])?;
+    conn.execute(&format!("DELETE FROM precinct_party_ballots WHERE precinctId IN ({})", precincts), [election_id])?;
+    conn.execute(&format!("DELETE FROM precinct WHERE id IN ({})", precincts), [election_id])?;
+    conn.execute("DELETE FROM municipality_code WHERE municipalId IN (SELECT id FROM municipality WHERE electionId = ?1)", [election_id])?;
+    conn.execute("DELETE FROM municipality WHERE electionId = ?1", [election_id])?;
+    conn.execute("DELETE FROM county WHERE electionId = ?1", [election_id])?;
+
+    Ok(())
+}
+
+/// The turnout read for a precinct from the results sheets.
+#[derive(Default)]
+struct PrecinctStats {
+    registered_voters: Option<i64>,
+    ballots_cast: Option<i64>,
+    party_ballots: Vec<(&'static str, i64)> // (party code, ballots cast) in primaries
+}
+
+/// The columns holding each party's ballots cast in a primary sheet: those given in the layout, or
+/// else the turnout columns before the candidates headed like `Democratic Ballots`.
+fn party_ballots_columns(layout: &crate::layout::Layout, sheet: &calamine::Range<calamine::Data>) -> Vec<(&'static str, u32)> {
+    if !layout.party_ballots_columns.is_empty() {
+        return layout.party_ballots_columns.iter().filter_map(|(party, col)| Some((party_code(party)?, *col))).collect();
+    }
+
+    let turnout = [layout.county_column, layout.precinct_column, layout.registered_voters_column, layout.ballots_cast_column];
+    (0..layout.first_candidate_column).filter(|col| !turnout.contains(col)).filter_map(|col| {
+        let header = sheet.get_value((layout.candidate_row, col))?.to_string();
+        let party = detect_ballot_party(&header).filter(|_| header.to_lowercase().contains("ballots"))?;
+        Some((party, col))
+    }).collect()
+}
+
+/// Party codes stored for candidates and party ballots, each with the abbreviations written in
+/// parentheses and the names that mark its ballot anywhere in a sheet or office name.
+const PARTIES: &[(&str, &[&str], &[&str])] = &[
+    ("D", &["D", "DEM"], &["DEMOCRATIC", "DEMOCRAT"]),
+    ("R", &["R", "REP", "GOP"], &["REPUBLICAN"]),
+    ("L", &["L", "LIB"], &["LIBERTARIAN"]),
+    ("G", &["G", "GRN", "GREEN"], &[]), // Green is only a name when followed by Party
+];
+
+/// The party code for an abbreviation such as `DEM` or `GRN`, or nothing if it is not a known party.
+pub fn party_code(abbreviation: &str) -> Option<&'static str> {
+    let abbreviation = abbreviation.trim().to_uppercase();
+    PARTIES.iter().find(|(_, abbreviations, _)| abbreviations.contains(&abbreviation.as_str())).map(|(code, _, _)| *code)
+}
+
+/// Splits the party off a candidate header, e.g. `Nan Whaley and Cheryl L. Stephens (D)`.
+/// Headers without a party suffix, such as ballot issue choices, keep their full name.
+/// Known abbreviations are stored as their party code, others as written.
+pub fn split_party(header: &str) -> (String, Option<String>) {
+    let header = header.trim();
+    if let Some((name, party)) = header.strip_suffix(')').and_then(|rest| rest.rsplit_once('(')) {
+        if !party.is_empty() && party.len() <= 3 && party.chars().all(|c| c.is_ascii_uppercase()) {
+            return (name.trim_end().to_string(), Some(party_code(party).unwrap_or(party).to_string()));
+        }
+    }
+
+    (header.to_string(), None)
+}
+
+/// The party ballot named in a primary sheet or office, e.g. `Democratic Statewide`, `Green Party`
+/// or `Governor (REP)`, as the party code used for candidates. Abbreviations are only taken in
+/// parentheses and Green only as `Green Party`, as Ohio has a city of Green, many Green Townships
+/// and offices such as `State Rep`.
+fn detect_ballot_party(text: &str) -> Option<&'static str> {
+    let text = text.to_uppercase();
+    let words: Vec<&str> = text.split(|c: char| !c.is_ascii_alphabetic()).filter(|word| !word.is_empty()).collect();
+    let named = words.iter().enumerate().find_map(|(idx, word)| match *word {
+        "GREEN" if words.get(idx + 1) == Some(&"PARTY") => Some("G"),
+        word => PARTIES.iter().find(|(_, _, names)| names.contains(&word)).map(|(code, _, _)| *code)
+    });
+
+    named.or_else(|| text.split('(').skip(1).find_map(|marked| party_code(marked.split(')').next()?)))
+}
+
+/// The number of seats an office elects, from headers like `Member of Council (Vote for not more than 3)`.
+pub fn parse_seats(office: &str) -> u32 {
+    let office = office.to_lowercase();
+    office.find("vote for")
+        .and_then(|at| office[at..].split_whitespace().find_map(|word| word.trim_matches(|c: char| !c.is_ascii_digit()).parse().ok()))
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// Which side of a ballot issue a choice is on: `Some(true)` for Yes/For, `Some(false)` for No/Against.
+pub fn issue_choice(name: &str) -> Option<bool> {
+    match name.split_whitespace().next()?.to_lowercase().as_str() {
+        "yes" | "for" => Some(true),
+        "no" | "against" => Some(false),
+        _ => None
+    }
+}
+
+/// Ballot issues and levies are offices with exactly two choices, Yes and No or For and Against.
+pub fn is_issue(candidates: &[String]) -> bool {
+    let mut sides: Vec<Option<bool>> = candidates.iter().map(|c| issue_choice(c)).collect();
+    sides.sort();
+    sides == [Some(false), Some(true)]
+}
+
+/// Reads a count such as the votes of a candidate or the registered voters of a precinct.
+/// Empty cells have no count; anything but a non-negative whole number is an error.
+fn read_count(cell: Option<&calamine::Data>) -> Result<Option<i64>, String> {
+    use calamine::Data;
+
+    match cell {
+        None | Some(Data::Empty) => Ok(None),
+        Some(Data::Int(count)) if *count >= 0 => Ok(Some(*count)),
+        Some(Data::Float(count)) if *count >= 0.0 && count.fract() == 0.0 => Ok(Some(*count as i64)),
+        Some(Data::String(count)) if count.trim().is_empty() => Ok(None),
+        Some(Data::String(count)) => match count.trim().parse::<i64>() {
+            Ok(count) if count >= 0 => Ok(Some(count)),
+            _ => Err(format!("Expected a whole number, found {}", count))
+        },
+        Some(other) => Err(format!("Expected a whole number, found {}", other))
+    }
+}
+
+/// Reads a date such as `November 8, 2022` from the start of the input, returning the rest of it.
+fn extract_date_and_remainder(input: &str) -> Result<(chrono::NaiveDate, &str), chrono::ParseError> {
+    chrono::NaiveDate::parse_and_remainder(input.trim_start(), "%B %d, %Y")
 }
 
 pub fn find_matching_files(dir: &std::path::Path, pattern: &str) -> Vec<std::path::PathBuf> {
@@ -458,4 +1004,121 @@ pub fn find_matching_files(dir: &std::path::Path, pattern: &str) -> Vec<std::pat
     }
 
     results
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn party_is_split_off_candidate_headers() {
+        assert_eq!(split_party("Nan Whaley and Cheryl L. Stephens (D)"), ("Nan Whaley and Cheryl L. Stephens".to_string(), Some("D".to_string())));
+        assert_eq!(split_party("Mike DeWine and Jon Husted (R)"), ("Mike DeWine and Jon Husted".to_string(), Some("R".to_string())));
+        assert_eq!(split_party(" Eric Brakey (GRN) "), ("Eric Brakey".to_string(), Some("G".to_string())));
+        assert_eq!(split_party("Jane Doe (NAT)"), ("Jane Doe".to_string(), Some("NAT".to_string())));
+    }
+
+    #[test]
+    fn headers_without_a_party_keep_their_name() {
+        assert_eq!(split_party("Yes"), ("Yes".to_string(), None));
+        assert_eq!(split_party("Marcy Kaptur"), ("Marcy Kaptur".to_string(), None));
+        assert_eq!(split_party("Tim Ryan (WI)*"), ("Tim Ryan (WI)*".to_string(), None));
+        assert_eq!(split_party("Judge (Unexpired Term)"), ("Judge (Unexpired Term)".to_string(), None));
+    }
+
+    #[test]
+    fn counts_are_non_negative_whole_numbers() {
+        use calamine::Data;
+
+        assert_eq!(read_count(Some(&Data::Int(1204))), Ok(Some(1204)));
+        assert_eq!(read_count(Some(&Data::Float(1204.0))), Ok(Some(1204)));
+        assert_eq!(read_count(Some(&Data::String(" 87 ".to_string()))), Ok(Some(87)));
+        assert_eq!(read_count(Some(&Data::Int(0))), Ok(Some(0)));
+    }
+
+    #[test]
+    fn empty_cells_have_no_count() {
+        use calamine::Data;
+
+        assert_eq!(read_count(None), Ok(None));
+        assert_eq!(read_count(Some(&Data::Empty)), Ok(None));
+        assert_eq!(read_count(Some(&Data::String("  ".to_string()))), Ok(None));
+    }
+
+    #[test]
+    fn other_counts_are_errors() {
+        use calamine::Data;
+
+        assert!(read_count(Some(&Data::String("1,204".to_string()))).is_err());
+        assert!(read_count(Some(&Data::String("-".to_string()))).is_err());
+        assert!(read_count(Some(&Data::Int(-3))).is_err());
+        assert!(read_count(Some(&Data::Float(12.5))).is_err());
+        assert!(read_count(Some(&Data::Bool(true))).is_err());
+    }
+
+    #[test]
+    fn yes_no_and_for_against_offices_are_issues() {
+        let issue = |choices: &[&str]| is_issue(&choices.iter().map(|c| c.to_string()).collect::<Vec<_>>());
+
+        assert!(issue(&["Yes", "No"]));
+        assert!(issue(&["No", "Yes"]));
+        assert!(issue(&["For the Tax Levy", "Against the Tax Levy"]));
+        assert!(issue(&["YES", "NO"]));
+    }
+
+    #[test]
+    fn candidate_offices_are_not_issues() {
+        let issue = |choices: &[&str]| is_issue(&choices.iter().map(|c| c.to_string()).collect::<Vec<_>>());
+
+        assert!(!issue(&["Mike DeWine and Jon Husted (R)", "Nan Whaley and Cheryl L. Stephens (D)"]));
+        assert!(!issue(&["Yes"]));
+        assert!(!issue(&["Yes", "Yes"]));
+        assert!(!issue(&["Yes", "No", "Noah Smith"]));
+        assert!(!issue(&["Forrest Burt", "Noelle Adams"]));
+    }
+
+    #[test]
+    fn seats_are_read_from_vote_for_not_more_than() {
+        assert_eq!(parse_seats("County Commissioner (Vote for not more than 2)"), 2);
+        assert_eq!(parse_seats("Member of Board of Education (Vote For Not More Than 3)"), 3);
+        assert_eq!(parse_seats("Member of Council at Large - Vote for 4"), 4);
+    }
+
+    #[test]
+    fn offices_without_a_count_have_one_seat() {
+        assert_eq!(parse_seats("Governor and Lieutenant Governor"), 1);
+        assert_eq!(parse_seats("Judge of the Court of Appeals 9th District (Full term commencing 2/9/2023)"), 1);
+        assert_eq!(parse_seats("Township Trustee (Vote for not more than 0)"), 1);
+    }
+
+    #[test]
+    fn party_ballots_are_named_or_marked() {
+        assert_eq!(detect_ballot_party("Democratic Statewide"), Some("D"));
+        assert_eq!(detect_ballot_party("Republican Congressional"), Some("R"));
+        assert_eq!(detect_ballot_party("Governor (REP)"), Some("R"));
+        assert_eq!(detect_ballot_party("State Central Committeeman - 28th District (DEM)"), Some("D"));
+        assert_eq!(detect_ballot_party("Governor ( LIB )"), Some("L"));
+        assert_eq!(detect_ballot_party("Green Party Statewide"), Some("G"));
+        assert_eq!(detect_ballot_party("Governor (GRN)"), Some("G"));
+        assert_eq!(detect_ballot_party("Governor (GREEN)"), Some("G"));
+    }
+
+    #[test]
+    fn places_and_abbreviations_are_not_party_ballots() {
+        assert_eq!(detect_ballot_party("Green Township Trustee"), None);
+        assert_eq!(detect_ballot_party("Member of Council - City of Green Ward 2"), None);
+        assert_eq!(detect_ballot_party("Green Local School District Board of Education"), None);
+        assert_eq!(detect_ballot_party("State Rep - 33rd District"), None);
+        assert_eq!(detect_ballot_party("Lib Township Fiscal Officer"), None);
+        assert_eq!(detect_ballot_party("Dem Ward 4 Council"), None);
+        assert_eq!(detect_ballot_party("Judge of the Court of Common Pleas (Full Term)"), None);
+    }
+
+    #[test]
+    fn candidates_and_ballots_share_party_codes() {
+        for abbreviation in ["D", "DEM", "R", "REP", "GOP", "L", "LIB", "G", "GRN"] {
+            let (_, party) = split_party(&format!("Jane Doe ({})", abbreviation));
+            assert_eq!(party.as_deref(), detect_ballot_party(&format!("Governor ({})", abbreviation)), "{}", abbreviation);
+        }
+    }
+}
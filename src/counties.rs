@@ -0,0 +1,110 @@
+/// One of Ohio's 88 counties.
+pub struct OhioCounty {
+    /// The 3-letter code ODOT maps use in `COUNTY_CD`, which is also the county abbreviation of
+    /// municipal-codes.xlsx and precinct-conversions#counties.
+    pub code: &'static str,
+    /// The name as the Secretary of State writes it in the election results.
+    pub name: &'static str,
+    /// The 5-digit state and county FIPS code, e.g. `39153` for Summit.
+    pub fips: &'static str,
+}
+
+pub const COUNTIES: [OhioCounty; 88] = [
+    OhioCounty { code: "ADA", name: "Adams", fips: "39001" },
+    OhioCounty { code: "ALL", name: "Allen", fips: "39003" },
+    OhioCounty { code: "ASD", name: "Ashland", fips: "39005" },
+    OhioCounty { code: "ATB", name: "Ashtabula", fips: "39007" },
+    OhioCounty { code: "ATH", name: "Athens", fips: "39009" },
+    OhioCounty { code: "AUG", name: "Auglaize", fips: "39011" },
+    OhioCounty { code: "BEL", name: "Belmont", fips: "39013" },
+    OhioCounty { code: "BRO", name: "Brown", fips: "39015" },
+    OhioCounty { code: "BUT", name: "Butler", fips: "39017" },
+    OhioCounty { code: "CAR", name: "Carroll", fips: "39019" },
+    OhioCounty { code: "CHP", name: "Champaign", fips: "39021" },
+    OhioCounty { code: "CLA", name: "Clark", fips: "39023" },
+    OhioCounty { code: "CLE", name: "Clermont", fips: "39025" },
+    OhioCounty { code: "CLI", name: "Clinton", fips: "39027" },
+    OhioCounty { code: "COL", name: "Columbiana", fips: "39029" },
+    OhioCounty { code: "COS", name: "Coshocton", fips: "39031" },
+    OhioCounty { code: "CRA", name: "Crawford", fips: "39033" },
+    OhioCounty { code: "CUY", name: "Cuyahoga", fips: "39035" },
+    OhioCounty { code: "DAR", name: "Darke", fips: "39037" },
+    OhioCounty { code: "DEF", name: "Defiance", fips: "39039" },
+    OhioCounty { code: "DEL", name: "Delaware", fips: "39041" },
+    OhioCounty { code: "ERI", name: "Erie", fips: "39043" },
+    OhioCounty { code: "FAI", name: "Fairfield", fips: "39045" },
+    OhioCounty { code: "FAY", name: "Fayette", fips: "39047" },
+    OhioCounty { code: "FRA", name: "Franklin", fips: "39049" },
+    OhioCounty { code: "FUL", name: "Fulton", fips: "39051" },
+    OhioCounty { code: "GAL", name: "Gallia", fips: "39053" },
+    OhioCounty { code: "GEA", name: "Geauga", fips: "39055" },
+    OhioCounty { code: "GRE", name: "Greene", fips: "39057" },
+    OhioCounty { code: "GUE", name: "Guernsey", fips: "39059" },
+    OhioCounty { code: "HAM", name: "Hamilton", fips: "39061" },
+    OhioCounty { code: "HAN", name: "Hancock", fips: "39063" },
+    OhioCounty { code: "HAR", name: "Hardin", fips: "39065" },
+    OhioCounty { code: "HAS", name: "Harrison", fips: "39067" },
+    OhioCounty { code: "HEN", name: "Henry", fips: "39069" },
+    OhioCounty { code: "HIG", name: "Highland", fips: "39071" },
+    OhioCounty { code: "HOC", name: "Hocking", fips: "39073" },
+    OhioCounty { code: "HOL", name: "Holmes", fips: "39075" },
+    OhioCounty { code: "HUR", name: "Huron", fips: "39077" },
+    OhioCounty { code: "JAC", name: "Jackson", fips: "39079" },
+    OhioCounty { code: "JEF", name: "Jefferson", fips: "39081" },
+    OhioCounty { code: "KNO", name: "Knox", fips: "39083" },
+    OhioCounty { code: "LAK", name: "Lake", fips: "39085" },
+    OhioCounty { code: "LAW", name: "Lawrence", fips: "39087" },
+    OhioCounty { code: "LIC", name: "Licking", fips: "39089" },
+    OhioCounty { code: "LOG", name: "Logan", fips: "39091" },
+    OhioCounty { code: "LOR", name: "Lorain", fips: "39093" },
+    OhioCounty { code: "LUC", name: "Lucas", fips: "39095" },
+    OhioCounty { code: "MAD", name: "Madison", fips: "39097" },
+    OhioCounty { code: "MAH", name: "Mahoning", fips: "39099" },
+    OhioCounty { code: "MAR", name: "Marion", fips: "39101" },
+    OhioCounty { code: "MED", name: "Medina", fips: "39103" },
+    OhioCounty { code: "MEG", name: "Meigs", fips: "39105" },
+    OhioCounty { code: "MER", name: "Mercer", fips: "39107" },
+    OhioCounty { code: "MIA", name: "Miami", fips: "39109" },
+    OhioCounty { code: "MOE", name: "Monroe", fips: "39111" },
+    OhioCounty { code: "MOT", name: "Montgomery", fips: "39113" },
+    OhioCounty { code: "MRG", name: "Morgan", fips: "39115" },
+    OhioCounty { code: "MRW", name: "Morrow", fips: "39117" },
+    OhioCounty { code: "MUS", name: "Muskingum", fips: "39119" },
+    OhioCounty { code: "NOB", name: "Noble", fips: "39121" },
+    OhioCounty { code: "OTT", name: "Ottawa", fips: "39123" },
+    OhioCounty { code: "PAU", name: "Paulding", fips: "39125" },
+    OhioCounty { code: "PER", name: "Perry", fips: "39127" },
+    OhioCounty { code: "PIC", name: "Pickaway", fips: "39129" },
+    OhioCounty { code: "PIK", name: "Pike", fips: "39131" },
+    OhioCounty { code: "POR", name: "Portage", fips: "39133" },
+    OhioCounty { code: "PRE", name: "Preble", fips: "39135" },
+    OhioCounty { code: "PUT", name: "Putnam", fips: "39137" },
+    OhioCounty { code: "RIC", name: "Richland", fips: "39139" },
+    OhioCounty { code: "ROS", name: "Ross", fips: "39141" },
+    OhioCounty { code: "SAN", name: "Sandusky", fips: "39143" },
+    OhioCounty { code: "SCI", name: "Scioto", fips: "39145" },
+    OhioCounty { code: "SEN", name: "Seneca", fips: "39147" },
+    OhioCounty { code: "SHE", name: "Shelby", fips: "39149" },
+    OhioCounty { code: "STA", name: "Stark", fips: "39151" },
+    OhioCounty { code: "SUM", name: "Summit", fips: "39153" },
+    OhioCounty { code: "TRU", name: "Trumbull", fips: "39155" },
+    OhioCounty { code: "TUS", name: "Tuscarawas", fips: "39157" },
+    OhioCounty { code: "UNI", name: "Union", fips: "39159" },
+    OhioCounty { code: "VAN", name: "Van Wert", fips: "39161" },
+    OhioCounty { code: "VIN", name: "Vinton", fips: "39163" },
+    OhioCounty { code: "WAR", name: "Warren", fips: "39165" },
+    OhioCounty { code: "WAS", name: "Washington", fips: "39167" },
+    OhioCounty { code: "WAY", name: "Wayne", fips: "39169" },
+    OhioCounty { code: "WIL", name: "Williams", fips: "39171" },
+    OhioCounty { code: "WOO", name: "Wood", fips: "39173" },
+    OhioCounty { code: "WYA", name: "Wyandot", fips: "39175" },
+];
+
+pub fn by_code(code: &str) -> Option<&'static OhioCounty> {
+    COUNTIES.iter().find(|county| county.code.eq_ignore_ascii_case(code.trim()))
+}
+
+/// Names are compared ignoring case, as the SOS capitalizes them in some sheets.
+pub fn by_name(name: &str) -> Option<&'static OhioCounty> {
+    COUNTIES.iter().find(|county| county.name.eq_ignore_ascii_case(name.trim()))
+}
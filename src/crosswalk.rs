@@ -0,0 +1,166 @@
+use calamine::{Data, Range};
+use std::collections::HashMap;
+
+/// Previous names by (county name, precinct name), with how each was given: `alias` or `manual`.
+pub type PreviousNames = HashMap<(String, String), (String, &'static str)>;
+
+/// Reads the names precincts had in the previous election from precinct-conversions#changes, with
+/// the number of renames left unconfirmed. The renames found by
+/// extract-municipal-codes are only suggestions, since names a letter apart are usually different
+/// precincts (`AKRON 1-B` and `AKRON 1-C`): they are linked as aliases once their status is changed to
+/// `confirmed`. A previous name written on a row of any other status is a manual mapping.
+pub fn read_previous_names(changes: &Range<Data>) -> (PreviousNames, usize) {
+    let mut unconfirmed = 0;
+    let previous_names = changes.rows().skip(1).filter_map(|row| {
+        let [status, county, precinct, previous, ..] = row else { return None };
+        let previous = previous.to_string();
+        if previous.trim().is_empty() {
+            return None;
+        }
+
+        let method = match status.to_string().trim().to_lowercase().as_str() {
+            "renamed" => {
+                unconfirmed += 1;
+                return None;
+            },
+            "confirmed" => "alias",
+            _ => "manual"
+        };
+        Some(((county.to_string(), precinct.to_string()), (previous, method)))
+    }).collect();
+
+    (previous_names, unconfirmed)
+}
+
+/// Stores the previous names of the precincts of an election for `link_precincts`.
+pub fn save_previous_names(conn: &rusqlite::Connection, precinct_ids: &HashMap<(String, String), i64>, previous_names: &PreviousNames) -> rusqlite::Result<()> {
+    for (key, (previous, method)) in previous_names {
+        if let Some(precinct_id) = precinct_ids.get(key) {
+            conn.prepare_cached("INSERT INTO precinct_alias(precinctId, previousName, method) VALUES(?1, ?2, ?3)")?.execute((precinct_id, previous, method))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Links precincts to the same precinct in earlier elections, oldest first, so that each precinct
+/// shares the `trackId` of its first appearance. A precinct is the same as the most recent earlier
+/// one of its county with its name, or with its previous name if it has one. Only the elections
+/// from `since` (a date and election id) on are relinked, as no earlier link depends on them;
+/// `None` relinks every election.
+pub fn link_precincts(conn: &rusqlite::Connection, since: Option<(&str, i64)>) -> rusqlite::Result<()> {
+    let later = "coalesce(?1 IS NULL OR e.date > ?1 OR (e.date = ?1 AND e.id >= ?2), 0)"; // undated elections sort first
+    let (date, id) = since.unzip();
+    conn.execute(&format!("
+        DELETE FROM precinct_crosswalk WHERE precinctId IN (
+            SELECT p.id FROM precinct p JOIN county c ON p.countyId = c.id JOIN election_info e ON c.electionId = e.id WHERE {}
+        )
+    ", later), (date, id))?;
+
+    // the earlier elections keep their links and are only read for the precincts the later ones follow
+    let mut latest: HashMap<(String, String), (i64, i64)> = HashMap::new(); // (county name, precinct name) -> (precinctId, trackId)
+    let earlier = conn.prepare(&format!("
+        SELECT p.id, c.name, p.name, x.trackId FROM precinct p
+        JOIN county c ON p.countyId = c.id JOIN election_info e ON c.electionId = e.id
+        JOIN precinct_crosswalk x ON x.precinctId = p.id
+        WHERE NOT {}
+        ORDER BY e.date, e.id
+    ", later))?.query_map((date, id), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+        .collect::<rusqlite::Result<Vec<(i64, String, String, i64)>>>()?;
+    for (id, county, name, track_id) in earlier {
+        latest.insert((county, name), (id, track_id));
+    }
+
+    let precincts = conn.prepare(&format!("
+        SELECT p.id, c.name, p.name, a.previousName, a.method FROM precinct p
+        JOIN county c ON p.countyId = c.id JOIN election_info e ON c.electionId = e.id
+        LEFT JOIN precinct_alias a ON a.precinctId = p.id
+        WHERE {}
+        ORDER BY e.date, e.id
+    ", later))?.query_map((date, id), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)))?
+        .collect::<rusqlite::Result<Vec<(i64, String, String, Option<String>, Option<String>)>>>()?;
+
+    for (id, county, name, previous_name, method) in precincts {
+        let previous = latest.get(&(county.clone(), previous_name.unwrap_or(name.clone()))).copied();
+        let (previous_id, track_id, method) = match previous {
+            Some((previous_id, track_id)) => (Some(previous_id), track_id, Some(method.unwrap_or("name".to_string()))),
+            None => (None, id, None)
+        };
+
+        conn.prepare_cached("INSERT INTO precinct_crosswalk(precinctId, previousId, trackId, method) VALUES(?1, ?2, ?3, ?4)")?.execute((id, previous_id, track_id, method))?;
+        latest.insert((county, name), (id, track_id));
+    }
+
+    Ok(())
+}
+
+/// Records each FIPS code of a municipality, so merged municipalities are found by any of theirs.
+pub fn save_municipal_codes(conn: &rusqlite::Connection, municipal_id: i64, fips: &str) -> rusqlite::Result<()> {
+    for code in fips.split(',') {
+        conn.prepare_cached("INSERT INTO municipality_code(municipalId, fips) VALUES(?1, ?2)")?.execute((municipal_id, code))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_confirmed_renames_are_linked() {
+        let rows = [
+            ["status", "county", "precinct", "previous name"],
+            ["renamed", "Summit", "AKRON 1-C", "AKRON 1-B"],
+            ["confirmed", "Summit", "BATH TWP A-1", "BATH TWP A"],
+            ["new", "Summit", "COPLEY NORTH", "COPLEY 3"],
+            ["new", "Summit", "FAIRLAWN 5", ""],
+            ["removed", "Summit", "AKRON 1-B", ""],
+        ];
+        let mut changes = Range::new((0, 0), (rows.len() as u32 - 1, 3));
+        for (row, values) in rows.iter().enumerate() {
+            for (col, value) in values.iter().enumerate() {
+                changes.set_value((row as u32, col as u32), Data::String(value.to_string()));
+            }
+        }
+
+        let (previous_names, unconfirmed) = read_previous_names(&changes);
+        assert_eq!(unconfirmed, 1);
+        assert_eq!(previous_names.len(), 2);
+        assert_eq!(previous_names[&("Summit".to_string(), "BATH TWP A-1".to_string())], ("BATH TWP A".to_string(), "alias"));
+        assert_eq!(previous_names[&("Summit".to_string(), "COPLEY NORTH".to_string())], ("COPLEY 3".to_string(), "manual"));
+    }
+
+    fn crosswalk(conn: &rusqlite::Connection) -> Vec<(i64, Option<i64>, i64, Option<String>)> {
+        conn.prepare("SELECT precinctId, previousId, trackId, method FROM precinct_crosswalk ORDER BY precinctId").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))).unwrap()
+            .collect::<rusqlite::Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn only_the_elections_from_the_given_one_are_relinked() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::schema::migrate(&conn).unwrap();
+        conn.execute_batch("
+            INSERT INTO election_info(id, name, date, map) VALUES(1, '2020 General Election', '2020-11-03', ''), (2, '2022 General Election', '2022-11-08', ''), (3, '2024 General Election', '2024-11-05', '');
+            INSERT INTO county(id, name, electionId) VALUES(1, 'Summit', 1), (2, 'Summit', 2), (3, 'Summit', 3);
+            INSERT INTO precinct(id, name, countyId) VALUES(1, 'AKRON 1-A', 1), (2, 'BATH TWP A', 1), (3, 'AKRON 1-A', 2), (4, 'BATH TWP A-1', 2), (5, 'AKRON 1-A', 3), (6, 'BATH TWP A-1', 3);
+            INSERT INTO precinct_alias(precinctId, previousName, method) VALUES(4, 'BATH TWP A', 'alias');
+        ").unwrap();
+
+        link_precincts(&conn, None).unwrap();
+        let linked = crosswalk(&conn);
+        assert_eq!(linked, [
+            (1, None, 1, None), (2, None, 2, None),
+            (3, Some(1), 1, Some("name".to_string())), (4, Some(2), 2, Some("alias".to_string())),
+            (5, Some(3), 1, Some("name".to_string())), (6, Some(4), 2, Some("name".to_string())),
+        ]);
+
+        // a mark on the first election shows it is left alone
+        conn.execute("UPDATE precinct_crosswalk SET method='manual' WHERE precinctId=1", ()).unwrap();
+        link_precincts(&conn, Some(("2022-11-08", 2))).unwrap();
+        let relinked = crosswalk(&conn);
+        assert_eq!(relinked[0], (1, None, 1, Some("manual".to_string())));
+        assert_eq!(relinked[1..], linked[1..]);
+    }
+}
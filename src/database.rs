@@ -0,0 +1,250 @@
+use crate::{emit, Log};
+use rusqlite::Connection;
+use crate::workspace::Workspace;
+use std::path::Path;
+
+/// Opens the database, reporting why if it cannot be or if it needs migrating first.
+pub fn connect(db: &Path) -> Option<Connection> {
+    open(db).filter(crate::schema::is_current)
+}
+
+/// Opens the database whatever its schema version.
+fn open(db: &Path) -> Option<Connection> {
+    use colored::Colorize;
+
+    if !db.exists() {
+        emit(Log::Error(format!("file does not exist: {}", db.display().to_string().underline())));
+        emit(Log::Info(format!("run the {} module", "init-database".underline())));
+        return None;
+    }
+
+    match Connection::open(db) {
+        Ok(conn) => Some(conn),
+        Err(why) => {
+            emit(Log::Error(format!("unable to establish connection: {}", why.to_string().underline())));
+            None
+        }
+    }
+}
+
+struct ElectionSummary {
+    id: i64,
+    name: String,
+    date: String,
+    kind: String,
+    map: String,
+    counties: i64,
+    municipalities: i64,
+    precincts: i64,
+    results: i64,
+}
+
+pub fn list_elections(db: &Path) {
+    use colored::Colorize;
+
+    let Some(conn) = connect(db) else { return };
+
+    let summaries = conn.prepare("
+        SELECT e.id, e.name, e.date, e.kind, e.map,
+            (SELECT count(*) FROM county WHERE electionId = e.id),
+            (SELECT count(*) FROM municipality WHERE electionId = e.id),
+            (SELECT count(*) FROM precinct p JOIN county c ON p.countyId = c.id WHERE c.electionId = e.id),
+            (SELECT count(*) FROM result r JOIN precinct p ON r.precinctId = p.id JOIN county c ON p.countyId = c.id WHERE c.electionId = e.id)
+        FROM election_info e ORDER BY e.date DESC
+    ").and_then(|mut stmt| stmt.query_map([], |row| {
+        Ok(ElectionSummary {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            date: row.get(2)?,
+            kind: row.get(3)?,
+            map: row.get(4)?,
+            counties: row.get(5)?,
+            municipalities: row.get(6)?,
+            precincts: row.get(7)?,
+            results: row.get(8)?,
+        })
+    })?.collect::<rusqlite::Result<Vec<_>>>());
+
+    let summaries = match summaries {
+        Ok(summaries) => summaries,
+        Err(why) => return emit(Log::Error(format!("Failed to list elections: {}", why.to_string().underline())))
+    };
+
+    if summaries.is_empty() {
+        return emit(Log::Info("no elections have been imported"));
+    }
+
+    let name_width = summaries.iter().map(|e| e.name.len()).max().unwrap_or(0).max(4);
+    let map_width = summaries.iter().map(|e| e.map.len()).max().unwrap_or(0).max(3);
+    println!("{}", format!("{:>4}  {:<name_width$}  {:<10}  {:<7}  {:<map_width$}  {:>8}  {:>14}  {:>9}  {:>9}", "id", "name", "date", "kind", "map", "counties", "municipalities", "precincts", "results").bold());
+    for e in summaries {
+        println!("{:>4}  {:<name_width$}  {:<10}  {:<7}  {:<map_width$}  {:>8}  {:>14}  {:>9}  {:>9}", e.id, e.name, e.date, e.kind, e.map, e.counties, e.municipalities, e.precincts, e.results);
+    }
+}
+
+pub fn delete_election(db: &Path, id: i64) {
+    use colored::Colorize;
+
+    let Some(mut conn) = connect(db) else { return };
+    if let Err(why) = conn.execute_batch("PRAGMA foreign_keys = ON") {
+        return emit(Log::Error(format!("unable to configure connection: {}", why.to_string().underline())));
+    }
+    let conn = conn.savepoint().unwrap();
+
+    let (name, date): (String, Option<String>) = match conn.query_row("SELECT name, date FROM election_info WHERE id=?1", [id], |row| Ok((row.get(0)?, row.get(1)?))) {
+        Ok(election) => election,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return emit(Log::Error(format!("No election exists with id={}", id))),
+        Err(why) => return emit(Log::Error(format!("Failed to look up election: {}", why.to_string().underline())))
+    };
+
+    println!("Deleting {} (id={})", name.underline(), id);
+    let deleted = crate::converter::delete_election_data(&conn, id)
+        .and_then(|_| conn.execute("DELETE FROM election_info WHERE id=?1", [id]))
+        .and_then(|_| crate::crosswalk::link_precincts(&conn, date.as_deref().map(|date| (date, id))));
+    if let Err(why) = deleted {
+        return emit(Log::Error(format!("Failed to delete election: {}", why.to_string().underline())));
+    }
+
+    conn.commit().unwrap();
+    println!("{} Successfully deleted {} from the database.", "Finished!".green().bold(), name.underline());
+}
+
+pub fn migrate(db: &Path) {
+    use colored::Colorize;
+
+    let Some(mut conn) = open(db) else { return };
+    let conn = conn.savepoint().unwrap();
+
+    let from = match crate::schema::migrate(&conn) {
+        Ok(from) => from,
+        Err(why) => return emit(Log::Error(why))
+    };
+    if from == crate::schema::latest_version() {
+        return println!("{} {} is already at schema version {}.", "Finished!".green().bold(), db.display().to_string().underline(), from);
+    }
+
+    // the index tables copy the columns of their views, which migrations may have changed
+    if let Err(why) = crate::converter::rebuild_indexes(&conn) {
+        return emit(Log::Error(format!("Failed to rebuild index tables: {}", why.to_string().underline())));
+    }
+
+    conn.commit().unwrap();
+    println!("{} Migrated {} from schema version {} to {}.", "Finished!".green().bold(), db.display().to_string().underline(), from, crate::schema::latest_version());
+}
+
+pub fn reindex(db: &Path) {
+    use colored::Colorize;
+
+    let Some(mut conn) = connect(db) else { return };
+    let conn = conn.savepoint().unwrap();
+
+    if let Err(why) = crate::converter::rebuild_indexes(&conn) {
+        return emit(Log::Error(format!("Failed to rebuild index tables: {}", why.to_string().underline())));
+    }
+
+    conn.commit().unwrap();
+    println!("{} Rebuilt the index tables of every election.", "Finished!".green().bold());
+}
+
+/// How many problems of each check are printed before the rest are only counted.
+const CHECK_SHOWN: usize = 20;
+
+/// Looks for what a half-finished or hand-edited import leaves behind: rows referring to rows that
+/// do not exist, candidates without results, precincts without a municipality, elections whose map
+/// is missing and index tables out of step with their views. Nothing is changed.
+pub fn check(workspace: &Workspace) {
+    use colored::Colorize;
+
+    let Some(conn) = connect(&workspace.db) else { return };
+
+    let checks: [(&str, rusqlite::Result<Vec<String>>); 5] = [
+        ("orphan rows", orphan_rows(&conn)),
+        ("candidates without results", candidates_without_results(&conn)),
+        ("precincts without a municipality", precincts_without_municipality(&conn)),
+        ("missing maps", missing_maps(&conn, workspace)),
+        ("index tables", index_mismatches(&conn)),
+    ];
+
+    let mut total = 0;
+    for (name, found) in checks {
+        let found = match found {
+            Ok(found) => found,
+            Err(why) => return emit(Log::Error(format!("Failed to check {}: {}", name, why.to_string().underline())))
+        };
+
+        if found.is_empty() {
+            println!("\t{} {}", name, "ok".green());
+            continue;
+        }
+
+        println!("\t{} {}", name, format!("{} found", found.len()).red());
+        for problem in found.iter().take(CHECK_SHOWN) {
+            emit(Log::Error(problem));
+        }
+        if found.len() > CHECK_SHOWN {
+            emit(Log::Info(format!("{} more {} not shown", found.len() - CHECK_SHOWN, name)));
+        }
+        total += found.len();
+    }
+
+    if total == 0 {
+        println!("{} No problems found in {}.", "Finished!".green().bold(), workspace.db.display().to_string().underline());
+    } else {
+        emit(Log::Error(format!("Found {} {} in {}", total, if total == 1 { "problem" } else { "problems" }, workspace.db.display().to_string().underline())));
+    }
+}
+
+/// Rows whose foreign keys refer to rows that do not exist.
+fn orphan_rows(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    conn.prepare("
+        SELECT f.\"table\", f.rowid, k.\"from\", f.parent FROM pragma_foreign_key_check() f
+        JOIN pragma_foreign_key_list(f.\"table\") k ON k.id = f.fkid
+    ")?.query_map([], |row| {
+        let (table, rowid, column, parent): (String, i64, String, String) = (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?);
+        Ok(format!("{} row {}: {} refers to a {} that does not exist", table, rowid, column, parent))
+    })?.collect()
+}
+
+fn candidates_without_results(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    conn.prepare("
+        SELECT e.name, e.id, o.name, c.name FROM candidate c
+        JOIN office_election o ON c.officeId = o.id JOIN office_category oc ON o.categoryId = oc.id JOIN election_info e ON oc.electionId = e.id
+        WHERE NOT EXISTS (SELECT 1 FROM result r WHERE r.candidateId = c.id)
+        ORDER BY e.date, e.id, o.id, c.id
+    ")?.query_map([], |row| {
+        let (election, id, office, candidate): (String, i64, String, String) = (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?);
+        Ok(format!("{} (id={}): {} for {} has no results", election, id, candidate, office))
+    })?.collect()
+}
+
+fn precincts_without_municipality(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    conn.prepare("
+        SELECT e.name, e.id, c.name, p.name FROM precinct p
+        JOIN county c ON p.countyId = c.id JOIN election_info e ON c.electionId = e.id
+        LEFT JOIN municipality m ON p.municipalId = m.id
+        WHERE m.id IS NULL
+        ORDER BY e.date, e.id, c.name, p.name
+    ")?.query_map([], |row| {
+        let (election, id, county, precinct): (String, i64, String, String) = (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?);
+        Ok(format!("{} (id={}): precinct {} of {} county has no municipality", election, id, precinct, county))
+    })?.collect()
+}
+
+/// Elections whose map directory, relative to the workspace root, does not exist.
+fn missing_maps(conn: &Connection, workspace: &Workspace) -> rusqlite::Result<Vec<String>> {
+    let elections = conn.prepare("SELECT name, id, map FROM election_info ORDER BY date, id")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<Vec<(String, i64, String)>>>()?;
+
+    Ok(elections.into_iter()
+        .filter(|(_, _, map)| !workspace.path(map).is_dir())
+        .map(|(election, id, map)| format!("{} (id={}): map directory {} does not exist", election, id, map))
+        .collect())
+}
+
+fn index_mismatches(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    Ok(crate::converter::compare_indexes(conn)?.into_iter()
+        .filter(|(_, missing, stale)| *missing > 0 || *stale > 0)
+        .map(|(table, missing, stale)| format!("{} is missing {} rows and has {} stale rows; run reindex", table, missing, stale))
+        .collect())
+}
@@ -0,0 +1,114 @@
+use crate::{emit, Log};
+use std::path::Path;
+
+/// A problem found in one of the import workbooks. Rows and columns are zero-based, as read.
+#[derive(serde::Serialize, Debug)]
+pub struct Diagnostic {
+    pub sheet: String,
+    pub row: Option<u32>,
+    pub column: Option<u32>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// The spreadsheet reference, e.g. `precinct-conversions#precincts!C12`.
+    pub fn location(&self) -> String {
+        match (self.row, self.column) {
+            (Some(row), Some(column)) => format!("{}!{}{}", self.sheet, column_name(column), row + 1),
+            (Some(row), None) => format!("{}!{}:{}", self.sheet, row + 1, row + 1),
+            (None, Some(column)) => format!("{}!{}:{}", self.sheet, column_name(column), column_name(column)),
+            (None, None) => self.sheet.clone()
+        }
+    }
+}
+
+/// Collects every problem found during an import so they can be reported together.
+#[derive(Default)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    pub fn error<S: Into<String>, M: Into<String>>(&mut self, sheet: S, row: Option<u32>, column: Option<u32>, message: M) {
+        self.items.push(Diagnostic {
+            sheet: sheet.into(),
+            row,
+            column,
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Prints every diagnostic followed by a count per sheet.
+    pub fn print(&self) {
+        use colored::Colorize;
+        use std::collections::BTreeMap;
+
+        let mut per_sheet: BTreeMap<&str, usize> = BTreeMap::new();
+        for item in &self.items {
+            emit(Log::Error(format!("{}: {}", item.location().underline(), item.message)));
+            *per_sheet.entry(item.sheet.as_str()).or_default() += 1;
+        }
+
+        for (sheet, count) in per_sheet {
+            emit(Log::Info(format!("{} problem(s) in {}", count, sheet.underline())));
+        }
+    }
+
+    /// Writes the diagnostics as JSON if `path` ends in `.json` and as a workbook otherwise.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        use colored::Colorize;
+
+        let failed = |why: String| format!("Failed to write {}: {}", path.display().to_string().underline(), why.underline());
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            let json = serde_json::to_string_pretty(&self.items).map_err(|why| failed(why.to_string()))?;
+            return std::fs::write(path, json).map_err(|why| failed(why.to_string()));
+        }
+
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let sheet = workbook.add_worksheet();
+        let bold = rust_xlsxwriter::Format::new().set_bold();
+        for (col, header) in ["sheet", "row", "column", "message"].iter().enumerate() {
+            sheet.write_with_format(0, col as u16, *header, &bold).map_err(|why| failed(why.to_string()))?;
+        }
+
+        for (idx, item) in self.items.iter().enumerate() {
+            let row = idx as u32 + 1;
+            sheet.write(row, 0, &item.sheet).map_err(|why| failed(why.to_string()))?;
+            if let Some(r) = item.row {
+                sheet.write(row, 1, r + 1).map_err(|why| failed(why.to_string()))?;
+            }
+            if let Some(c) = item.column {
+                sheet.write(row, 2, column_name(c)).map_err(|why| failed(why.to_string()))?;
+            }
+            sheet.write(row, 3, &item.message).map_err(|why| failed(why.to_string()))?;
+        }
+
+        workbook.save(path).map_err(|why| failed(why.to_string()))
+    }
+}
+
+/// Converts a zero-based column index to its spreadsheet letters (0 -> A, 27 -> AB).
+fn column_name(column: u32) -> String {
+    let mut column = column + 1;
+    let mut name = Vec::new();
+    while column > 0 {
+        let rem = (column - 1) % 26;
+        name.push(b'A' + rem as u8);
+        column = (column - 1) / 26;
+    }
+
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
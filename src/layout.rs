@@ -0,0 +1,130 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Where things are in a results workbook. Read from `layout.toml` next to the election results;
+/// any key left out keeps the value of the Secretary of State's current layout.
+///
+/// ```toml
+/// first_data_row = 3
+/// skip_sheets = ["Contents", "Master", "Notes"]
+///
+/// [issue_thresholds]
+/// "State Issue 1 - Elevating the Standards to Qualify for and to Pass Any Constitutional Amendment" = 0.6
+/// ```
+#[derive(serde::Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layout {
+    /// Row holding the office names, each above its first candidate.
+    pub office_row: u32,
+    /// Row holding the candidate names.
+    pub candidate_row: u32,
+    /// First row of precinct results.
+    pub first_data_row: u32,
+    /// First column of candidate results.
+    pub first_candidate_column: u32,
+    pub county_column: u32,
+    pub precinct_column: u32,
+    pub registered_voters_column: u32,
+    pub ballots_cast_column: u32,
+    /// Columns of each party's ballots cast in a primary, by party (e.g. `D = 8`). If none are
+    /// given, turnout columns headed like `Democratic Ballots` are used.
+    pub party_ballots_columns: HashMap<String, u32>,
+    /// Sheet listing every precinct, used by extract-municipal-codes.
+    pub master_sheet: String,
+    /// Sheets that are not imported as an office category.
+    pub skip_sheets: Vec<String>,
+    /// Offices imported as ballot issues and the share of Yes votes each needs to pass. Offices
+    /// choosing between Yes and No (or For and Against) are issues needing a majority regardless.
+    pub issue_thresholds: HashMap<String, f64>,
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout {
+            office_row: 0,
+            candidate_row: 1,
+            first_data_row: 4,
+            first_candidate_column: 8,
+            county_column: 0,
+            precinct_column: 1,
+            registered_voters_column: 5,
+            ballots_cast_column: 6,
+            party_ballots_columns: HashMap::new(),
+            master_sheet: "Master".to_string(),
+            skip_sheets: vec!["Contents".to_string(), "Master".to_string()],
+            issue_thresholds: HashMap::new(),
+        }
+    }
+}
+
+impl Layout {
+    /// Reads `layout.toml` from the election directory, or the default layout if there is none.
+    pub fn load(election_path: &Path) -> Result<Layout, String> {
+        use colored::Colorize;
+
+        let path = election_path.join("layout.toml");
+        if !path.exists() {
+            return Ok(Layout::default());
+        }
+
+        let contents = std::fs::read_to_string(&path).map_err(|why| format!("unable to open {}: {}", path.display().to_string().underline(), why.to_string().underline()))?;
+        let layout: Layout = toml::from_str(&contents).map_err(|why| format!("Failed to read {}: {}", path.display().to_string().underline(), why))?;
+        if let Some((office, threshold)) = layout.issue_thresholds.iter().find(|(_, t)| !(0.5..1.0).contains(*t)) {
+            return Err(format!("Threshold of {} in {} must be at least 0.5 and below 1, not {}", office.underline(), path.display().to_string().underline(), threshold));
+        }
+
+        if let Some(party) = layout.party_ballots_columns.keys().find(|party| crate::converter::party_code(party).is_none()) {
+            return Err(format!("Unknown party {} under party_ballots_columns in {}; use D, R, L or G", party.underline(), path.display().to_string().underline()));
+        }
+
+        Ok(layout)
+    }
+
+    pub fn skips(&self, sheet: &str) -> bool {
+        self.skip_sheets.iter().any(|s| s == sheet)
+    }
+
+    /// Office names are compared ignoring line breaks and repeated spaces, which the SOS headers are full of.
+    pub fn issue_threshold(&self, office: &str) -> Option<f64> {
+        let office = normalize_office(office);
+        self.issue_thresholds.iter()
+            .find(|(name, _)| normalize_office(name) == office)
+            .map(|(_, threshold)| *threshold)
+    }
+
+    /// Configured thresholds naming none of the given offices, most likely a typo or a renamed issue.
+    pub fn unmatched_thresholds<'a>(&'a self, offices: &[String]) -> Vec<&'a str> {
+        let offices: HashSet<String> = offices.iter().map(|office| normalize_office(office)).collect();
+        let mut unmatched: Vec<&str> = self.issue_thresholds.keys()
+            .filter(|name| !offices.contains(&normalize_office(name)))
+            .map(|name| name.as_str())
+            .collect();
+        unmatched.sort();
+        unmatched
+    }
+}
+
+fn normalize_office(office: &str) -> String {
+    office.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds_match_offices_ignoring_whitespace() {
+        let layout = Layout {
+            issue_thresholds: HashMap::from([
+                ("State Issue 1 -  Elevating the Standards".to_string(), 0.6),
+                ("State Issue 9".to_string(), 0.6),
+            ]),
+            ..Layout::default()
+        };
+        let offices = vec!["State Issue 1 -\nElevating the Standards".to_string(), "Governor".to_string()];
+
+        assert_eq!(layout.issue_threshold(&offices[0]), Some(0.6));
+        assert_eq!(layout.issue_threshold(&offices[1]), None);
+        assert_eq!(layout.unmatched_thresholds(&offices), vec!["State Issue 9"]);
+    }
+}
@@ -1,6 +1,16 @@
+mod assign;
 mod converter;
+mod counties;
+mod crosswalk;
+mod database;
+mod diagnostics;
+mod layout;
+mod map;
 mod router;
+mod schema;
+mod workspace;
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 
 #[derive(Parser)]
@@ -8,6 +18,12 @@ use clap::{Parser, Subcommand};
 struct Cli {
     #[command(subcommand)]
     module: Commands,
+
+    #[arg(long, global = true, env = "OEV_ROOT", value_name = "directory", default_value = ".", help = "The project directory holding elections/, maps/ and public/. Paths given to any module are relative to it.")]
+    root: PathBuf,
+
+    #[arg(long, global = true, env = "OEV_DB", value_name = "file", help = "The database to use instead of elections.db in the project directory.")]
+    db: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -18,13 +34,40 @@ enum Commands {
         force: bool
     },
 
+    #[command(about = "Builds the municipals Shapefile map from the ODOT township, city, and county maps.")]
+    BuildMunicipalMap {
+        #[arg(long, value_name = "directory", help = "Path to a directory containing the ODOT township shapefile (e.g. REFER_TOWNSHIP).")]
+        township_map: String,
+
+        #[arg(long, value_name = "directory", help = "Path to a directory containing the ODOT city shapefile (e.g. REFER_CITY).")]
+        city_map: String,
+
+        #[arg(long, value_name = "directory", help = "Path to a directory containing the ODOT county shapefile (e.g. REFER_COUNTY).")]
+        county_map: String,
+
+        #[arg(long, value_name = "directory", help = "Path to the directory the municipals shapefile is written to. The files therein are named after the directory.")]
+        map_path: String
+    },
+
     #[command(about = "Extracts municipal codes from a Shapefile map and creates a table.")]
     ExtractMunicipalCodes {
         #[arg(long, value_name = "directory", help = "The name of the direction in elections/ containing preinct-results.xlsx.\nFor example, 2022/general.")]
         election_path: String,
 
         #[arg(long, value_name = "directory", help = "Path to a directory containing the shapefile. The name of each file therein MUST match the name of the directory.")]
-        map_path: String
+        map_path: String,
+
+        #[arg(long, value_name = "directory", help = "Path to a directory containing a precinct boundary shapefile in the same projection as the map, used to assign precincts by overlap.\nMay be given once per county. The name of each file therein MUST match the name of the directory.")]
+        precinct_map: Vec<String>,
+
+        #[arg(long, value_name = "field", default_value = "NAME", help = "The field of the precinct shapefile holding the precinct name as it appears in the election results.")]
+        precinct_name_field: String,
+
+        #[arg(long, value_name = "fraction", default_value_t = 0.05, help = "The fraction of a precinct's area a municipality must cover to be assigned to it.")]
+        min_overlap: f64,
+
+        #[arg(long, value_name = "path or id", help = "A previous election, by its directory, its precinct-conversions.xlsx or its id in the database.\nPrecincts keeping their county and name keep its municipalities, and a changes sheet lists new, removed and renamed precincts.")]
+        from_election: Option<String>
     },
 
     #[command(about = "Load election results into the database.", name = "import-election")]
@@ -33,9 +76,57 @@ enum Commands {
         election_path: String,
 
         #[arg(long, value_name = "name", help = "The name of the election. Uses derived value otherwise.")]
-        name: Option<String>
+        name: Option<String>,
+
+        #[arg(long, value_name = "YYYY-MM-DD", help = "The date of the election. Read from cell A1 of the results otherwise.")]
+        date: Option<chrono::NaiveDate>,
+
+        #[arg(long, value_enum, help = "The kind of election. Read from cell A1 of the results otherwise.")]
+        kind: Option<converter::ElectionKind>,
+
+        #[arg(long, help = "If an election with the same name and date exists, replace its results instead of adding another.", action = clap::ArgAction::SetTrue)]
+        replace: bool,
+
+        #[arg(long, value_name = "id", requires = "replace", help = "The id of the election to replace, rather than finding it by name and date.")]
+        election_id: Option<i64>,
+
+        #[arg(long, help = "Only check the workbooks and report every problem found. Nothing is written to the database.", action = clap::ArgAction::SetTrue)]
+        check: bool,
+
+        #[arg(long, value_name = "file", help = "Write the problems found to this file, as JSON if it ends in .json and as a workbook otherwise.")]
+        report: Option<PathBuf>,
+
+        #[arg(long, help = "Import write-in candidates, marked (WI)*, instead of dropping them.", action = clap::ArgAction::SetTrue)]
+        keep_write_ins: bool
+    },
+
+    #[command(about = "Builds the election map from the municipals map and the filter/merge files written by import-election.")]
+    FinalizeMap {
+        #[arg(long, value_name = "directory", help = "The name of the directory in elections/ containing election-results.xlsx.\nFor example, 2022/general. The map is written to map/map.shp therein.")]
+        election_path: String,
+
+        #[arg(long, value_name = "directory", help = "Path to a directory containing the municipals shapefile. The name of each file therein MUST match the name of the directory.")]
+        map_path: String
+    },
+
+    #[command(about = "Lists the elections in the database.")]
+    ListElections,
+
+    #[command(about = "Removes an election and all of its results from the database.")]
+    DeleteElection {
+        #[arg(long, value_name = "id", help = "The id of the election, as shown by list-elections.")]
+        id: i64
     },
 
+    #[command(about = "Upgrades the schema of the database in place, keeping every imported election.")]
+    Migrate,
+
+    #[command(about = "Rebuilds the index tables the server reads for every election.")]
+    Reindex,
+
+    #[command(about = "Checks the database for orphan rows, candidates without results, precincts without a municipality, missing maps and stale index tables.")]
+    Check,
+
     #[command(about = "Launch a HTTP server on the given address.")]
     RunServer {
         #[arg(long, value_name = "bind address", help = "The IP and port to launch the server on.")]
@@ -43,75 +134,51 @@ enum Commands {
     },
 }
 
-fn raw_query<S: Into<String> + Copy>(conn: &rusqlite::Connection, q: S) {
-    query(conn, q, ())
-}
-
-fn query<S: Into<String> + Copy, P: rusqlite::Params>(conn: &rusqlite::Connection, query: S, params: P) {
-    use colored::Colorize;
-
-    match conn.execute(query.into().as_str(), params) {
-        Ok(_) => {},
-        Err(why) => {
-            emit(Log::Error(format!("failed to run query: {}", query.into().underline())));
-            emit(Log::Error(why.to_string()));
-        }
-    }
-}
-
 fn main() {
     use colored::Colorize;
-    use std::path::PathBuf;
 
     let cli = Cli::parse();
+    let workspace = workspace::Workspace::new(cli.root.clone(), cli.db.clone());
     match &cli.module {
         Commands::InitDatabase { force } => {
             use rusqlite::Connection;
 
-            let path = PathBuf::from("elections.db");
+            let path = &workspace.db;
             if path.exists() && !force {
                 emit(Log::Error("database already initialized"));
                 emit(Log::Info("run with --force argument to override"));
                 return;
             }
 
-            match std::fs::remove_file(path) {
-                Ok(_) => {},
-                Err(why) => return emit(Log::Error(format!("failed to remove {}: {}", "elections.db".underline(), why.to_string().underline())))
-            };
+            if path.exists() {
+                if let Err(why) = std::fs::remove_file(path) {
+                    return emit(Log::Error(format!("failed to remove {}: {}", path.display().to_string().underline(), why.to_string().underline())));
+                }
+            }
 
-            let mut conn = match Connection::open("elections.db") {
+            let mut conn = match Connection::open(path) {
                 Ok(conn) => conn,
                 Err(why) => return emit(Log::Error(format!("unable to establish connection: {}", why.to_string().underline())))
             };
 
             let conn = conn.savepoint().unwrap();
-            match conn.execute_batch("
-                CREATE TABLE election_info(id integer primary key autoincrement, name text, date date, map text);
-                CREATE TABLE county(id integer primary key autoincrement, name text, electionId integer, foreign key (electionId) references election_info(id));
-                CREATE TABLE municipality(id integer primary key autoincrement, name text, fips text, electionId integer, foreign key (electionId) references election_info(id));
-                CREATE TABLE precinct(id integer primary key autoincrement, name text, municipalId integer, countyId integer, foreign key (municipalId) references municipality(id), foreign key (countyId) references county(id));
-                CREATE TABLE office_category(id integer primary key autoincrement, name text, electionId integer, foreign key (electionId) references election_info(id));
-                CREATE TABLE office_election(id integer primary key autoincrement, name text, categoryId integer, foreign key (categoryId) references office_category(id));
-                CREATE TABLE candidate(id integer primary key autoincrement, name text, officeId integer, foreign key (officeId) references office_election(id));
-                CREATE TABLE result(id integer primary key autoincrement, votes integer, candidateId integer, precinctId integer, foreign key (candidateId) references candidate(id), foreign key (precinctId) references precinct(id));
-
-                CREATE VIEW state_results as select r.officeId, sum(r.votes) as votes, r.candidateId, r.candidateName from county_results r group by r.candidateId;
-                CREATE VIEW municipal_results as select m.id, r.officeId, sum(r.votes) as votes, r.candidateId, r.candidateName, m.name as municipalName, m.fips as municipalCode, m.electionId from precinct_results r join municipality m on r.municipalId = m.id group by r.candidateId, m.id;
-                CREATE VIEW county_results as select c.id, r.officeId, sum(r.votes) as votes, r.candidateId, r.candidateName, c.name as countyName from precinct_results r join county c on r.countyId = c.id group by r.candidateId, c.id;
-                CREATE VIEW precinct_results as select r.id, c.officeId, r.votes, r.candidateId, c.name as candidateName, p.id as precinctId, p.name as precinctName, p.municipalId, p.countyId from result r inner join candidate c on r.candidateId = c.id inner join precinct p on r.precinctId = p.id;
-            ") {
-                Ok(_) => {},
-                Err(why) => {
-                    return emit(Log::Error(format!("Failed to initialized database: {}", why.to_string().underline())));
-                }
-            };
+            if let Err(why) = schema::migrate(&conn) {
+                return emit(Log::Error(why));
+            }
             conn.commit().unwrap();
             println!("{} Database initialized.", "Success!".green().bold());
         },
 
-        Commands::ExtractMunicipalCodes { election_path, map_path } => {
-            let map_path: PathBuf = map_path.into();
+        Commands::ExtractMunicipalCodes { election_path, map_path, precinct_map, precinct_name_field, min_overlap, from_election } => {
+            // an existing precinct-conversions.xlsx is never overwritten, so these would be ignored
+            let conversions = workspace.path(election_path).join("precinct-conversions.xlsx");
+            if conversions.exists() && (!precinct_map.is_empty() || from_election.is_some()) {
+                emit(Log::Error(format!("{} already exists and would not be regenerated from --precinct-map or --from-election", conversions.display().to_string().underline())));
+                emit(Log::Info("move or remove it, or run again without those options to only extract the municipal codes"));
+                return;
+            }
+
+            let map_path = workspace.path(map_path);
 
             let general_name = match map_path.file_name() {
                 Some(name) => name.to_string_lossy(),
@@ -131,16 +198,15 @@ fn main() {
                 }
             };
 
-            let workbook_uri: PathBuf = [&election_path, "municipal-codes.xlsx"].iter().collect();
             let mut workbook = rust_xlsxwriter::Workbook::new();
 
-            let mut sheet = workbook.add_worksheet();
+            let sheet = workbook.add_worksheet();
 
             let mut reserve = TwoKeyMap::<String, String, String, String>::new();
 
-            for (idx, shape_record) in reader.iter_shapes_and_records().enumerate() {
-                let (shape, record) = match shape_record {
-                    Ok((shape, record)) => (shape, record),
+            for shape_record in reader.iter_shapes_and_records() {
+                let record = match shape_record {
+                    Ok((_, record)) => record,
                     Err(why) => {
                         emit(Log::Error(format!("{}", why)));
                         return;
@@ -196,89 +262,239 @@ fn main() {
                 sheet.write(idx, 1, r#type).unwrap();
             }
 
-            let workbook_uri: PathBuf = [&election_path, "precinct-conversions.xlsx"].iter().collect();
+            let workbook_uri = workspace.path(election_path).join("precinct-conversions.xlsx");
             if !workbook_uri.exists() {
                 emit(Log::Info("precinct-conversions.xlsx is missing, we will create it now"));
-                use std::io::Write;
                 use calamine::Reader;
 
-                let results = &converter::find_matching_files(&PathBuf::from(election_path), "election-results");
-                if results.len() == 0 {
+                let results = &converter::find_matching_files(&workspace.path(election_path), "election-results");
+                if results.is_empty() {
                     emit(Log::Warning(format!("{} was missing: precinct-conversions.xlsx cannot be generated", "election-results.xlsx".underline())));
                 } else {
+                    let layout = match layout::Layout::load(&workspace.path(election_path)) {
+                        Ok(layout) => layout,
+                        Err(why) => return emit(Log::Error(why))
+                    };
+
                     let mut result_precincts = calamine::open_workbook_auto(results[0].clone()).unwrap();
-                    let result_precincts = result_precincts.worksheet_range("Master");
-                    if result_precincts.is_ok() {
-                        let result_precincts = result_precincts.unwrap();
+                    if let Ok(result_precincts) = result_precincts.worksheet_range(&layout.master_sheet) {
 
                         let mut workbook = rust_xlsxwriter::Workbook::new();
 
                         {
+                            use rust_xlsxwriter::{Color, Format};
+
                             let precincts = workbook.add_worksheet().set_name("precincts").unwrap();
 
-                            for row in 4..result_precincts.get_size().0 {
+                            let first_row = layout.first_data_row;
+                            let county_names: Vec<String> = (first_row as usize..result_precincts.get_size().0).map(|row| result_precincts.get_value((row as u32, layout.county_column)).unwrap().to_string()).collect();
+                            let matcher = assign::NameMatcher::new(reserve.iter_ordered());
+                            let spatial = if precinct_map.is_empty() {
+                                None
+                            } else {
+                                let mut paths = Vec::new();
+                                for dir in precinct_map {
+                                    match map::shapefile_path(&workspace.path(dir)) {
+                                        Some(path) => paths.push(path),
+                                        None => return emit(Log::Error(format!("Failed to get filename for path {}", dir.underline())))
+                                    }
+                                }
+
+                                let municipals = match map::read_features(&map_path.join(general_name.clone()).with_extension("shp")) {
+                                    Ok(features) => features,
+                                    Err(why) => return emit(Log::Error(why))
+                                };
+
+                                match assign::SpatialMatcher::new(&paths, &municipals, precinct_name_field, *min_overlap) {
+                                    Ok(spatial) => Some(spatial),
+                                    Err(why) => return emit(Log::Error(why))
+                                }
+                            };
+
+                            let prior = match from_election.as_deref().map(|source| assign::PriorAssignments::load(source, &workspace)) {
+                                Some(Ok(prior)) => Some(prior),
+                                Some(Err(why)) => return emit(Log::Error(why)),
+                                None => None
+                            };
+                            let known_fips: std::collections::HashSet<String> = reserve.iter_ordered().map(|(_, _, fips, _)| fips.clone()).collect();
+                            let mut current = Vec::new();
+
+                            let review = Format::new().set_background_color(Color::Yellow);
+                            let unmatched = Format::new().set_background_color(Color::RGB(0xFFC7CE));
+                            let (mut assigned, mut flagged) = (0, 0);
+
+                            for row in first_row as usize..result_precincts.get_size().0 {
                                 let row = row as u32;
 
-                                let precinct_name = result_precincts.get_value((row, 1)).unwrap().to_string();
-                                let county = result_precincts.get_value((row, 0)).unwrap().to_string();
-                                precincts.write(row - 4, 0, county).unwrap();
-                                precincts.write(row - 4, 1, precinct_name).unwrap();
+                                let precinct_name = result_precincts.get_value((row, layout.precinct_column)).unwrap().to_string();
+                                let county = result_precincts.get_value((row, layout.county_column)).unwrap().to_string();
+
+                                if !precinct_name.is_empty() {
+                                    current.push((county.clone(), precinct_name.clone()));
+                                }
+
+                                // the FIPS codes go in column C onwards, followed by the confidence of the proposal
+                                let previously = prior.as_ref().and_then(|prior| prior.assign(&county, &precinct_name, &known_fips));
+                                let spatially = spatial.as_ref()
+                                    .zip(matcher.county_code(&county))
+                                    .and_then(|(spatial, code)| spatial.assign(code, &precinct_name))
+                                    .cloned();
+
+                                match previously.or(spatially).or_else(|| matcher.assign(&county, &precinct_name)) {
+                                    Some(assignment) => {
+                                        let col = 2 + assignment.fips.len() as u16;
+                                        for (idx, fips) in assignment.fips.iter().enumerate() {
+                                            precincts.write(row - first_row, 2 + idx as u16, fips).unwrap();
+                                        }
+
+                                        let confidence = format!("{:.0}%", assignment.confidence * 100.0);
+                                        if assignment.confidence < assign::Assignment::REVIEW_BELOW {
+                                            precincts.write_with_format(row - first_row, col, confidence, &review).unwrap();
+                                            flagged += 1;
+                                        } else {
+                                            precincts.write(row - first_row, col, confidence).unwrap();
+                                        }
+
+                                        assigned += 1;
+                                    },
+
+                                    None => {
+                                        precincts.write_blank(row - first_row, 2, &unmatched).unwrap();
+                                    }
+                                }
+
+                                precincts.write(row - first_row, 0, county).unwrap();
+                                precincts.write(row - first_row, 1, precinct_name).unwrap();
+                            }
+
+                            let total = county_names.len();
+                            emit(Log::Info(format!("{} of {} precincts were assigned automatically; {} low-confidence and {} unmatched precincts are highlighted for review", assigned, total, flagged, total - assigned)));
+
+                            if let Some(prior) = prior {
+                                let changes_sheet = workbook.add_worksheet().set_name("changes").unwrap();
+                                let bold = Format::new().set_bold();
+                                for (col, header) in ["status", "county", "precinct", "previous name", "previous FIPS"].iter().enumerate() {
+                                    changes_sheet.write_with_format(0, col as u16, *header, &bold).unwrap();
+                                }
+
+                                let changes = prior.changes(&current);
+                                for (idx, change) in changes.iter().enumerate() {
+                                    let row = idx as u32 + 1;
+                                    changes_sheet.write(row, 0, change.status).unwrap();
+                                    changes_sheet.write(row, 1, &change.county).unwrap();
+                                    changes_sheet.write(row, 2, &change.precinct).unwrap();
+                                    if let Some(previous) = &change.previous {
+                                        changes_sheet.write(row, 3, previous).unwrap();
+                                    }
+                                    for (col, fips) in change.fips.iter().enumerate() {
+                                        changes_sheet.write(row, 4 + col as u16, fips).unwrap();
+                                    }
+                                }
+
+                                let count = |status| changes.iter().filter(|change| change.status == status).count();
+                                emit(Log::Info(format!("Compared to the previous election: {} new, {} removed and {} renamed precincts, listed in the {} sheet", count("new"), count("removed"), count("renamed"), "changes".underline())));
+                                if count("renamed") > 0 {
+                                    emit(Log::Info(format!("renames are suggestions; change the status of each correct one to {} to link the precincts across elections", "confirmed".underline())));
+                                }
                             }
                         }
 
                         {
-                            workbook.add_worksheet().set_name("counties").unwrap();
+                            // codes of the map that are not Ohio's are left for their names to be written in
+                            let sheet = workbook.add_worksheet().set_name("counties").unwrap();
+                            let mut codes: Vec<String> = reserve.iter_ordered().map(|(county, _, _, _)| county).collect();
+                            codes.dedup();
+                            for (idx, code) in codes.iter().enumerate() {
+                                sheet.write(idx as u32, 0, code).unwrap();
+                                if let Some(county) = counties::by_code(code) {
+                                    sheet.write(idx as u32, 1, county.name).unwrap();
+                                }
+                            }
                         }
 
-                        let save_uri: PathBuf = [&election_path, "precinct-conversions.xlsx"].iter().collect();
+                        let save_uri = workspace.path(election_path).join("precinct-conversions.xlsx");
                         workbook.save(save_uri.clone()).unwrap();
                         println!("{} Successfully wrote and saved {}", "Finished!".green().bold(), save_uri.display().to_string().underline());
                     } else {
-                        emit(Log::Warning(format!("{} was misisng Master sheet: precinct-conversions.xlsx cannot be generated", results[0].display().to_string())));
+                        emit(Log::Warning(format!("{} was misisng {} sheet: precinct-conversions.xlsx cannot be generated", results[0].display(), layout.master_sheet)));
                     }
                 }
             } else {
                 println!("{} precinct-conversions.xlsx already exists", "Finished!".green().bold());
             }
 
-            let workbook_uri: PathBuf = [&election_path, "municipal-codes.xlsx"].iter().collect();
+            let workbook_uri = workspace.path(election_path).join("municipal-codes.xlsx");
             workbook.save(workbook_uri.clone()).unwrap();
             println!("{} Successfully wrote and saved {}", "Finished!".green().bold(), workbook_uri.display().to_string().underline());
         },
 
-        Commands::ElectionConverter { election_path, name } => converter::run(election_path.to_owned(), name),
+        Commands::BuildMunicipalMap { township_map, city_map, county_map, map_path } => map::build_municipal_map(&workspace, township_map, city_map, county_map, map_path),
+
+        Commands::ElectionConverter { election_path, name, date, kind, replace, election_id, check, report, keep_write_ins } => converter::run(&workspace, election_path.to_owned(), &converter::Options {
+            replace: *replace,
+            election_id: *election_id,
+            check: *check,
+            report: report.as_ref().map(|report| workspace.path(report)),
+            keep_write_ins: *keep_write_ins,
+            name: name.clone(),
+            date: *date,
+            kind: *kind,
+        }),
+
+        Commands::FinalizeMap { election_path, map_path } => map::finalize_map(&workspace, election_path, map_path),
+
+        Commands::ListElections => database::list_elections(&workspace.db),
+
+        Commands::DeleteElection { id } => database::delete_election(&workspace.db, *id),
+
+        Commands::Migrate => database::migrate(&workspace.db),
+
+        Commands::Reindex => database::reindex(&workspace.db),
+
+        Commands::Check => database::check(&workspace),
 
         Commands::RunServer { bind_to } => {
             use std::fs::File;
-            use std::collections::HashMap;
 
+            if database::connect(&workspace.db).is_none() {
+                return;
+            }
+
+            let db = workspace.db.clone();
             println!("Starting server on {}", bind_to);
             rouille::start_server(bind_to, move |request| {
                 let start = std::time::SystemTime::now();
 
                 let resp = rouille::router!(request, 
-                    (GET) ["/"] => rouille::Response::from_file("text/html", File::open("public/index.html").unwrap()),
-                    (GET) ["/elections.db"] => rouille::Response::from_file("application/octet-stream", File::open("elections.db").unwrap()),
-
-                    (GET) ["/elections/{year}/{election_class}/map/{file}", year: String, election_class: String, file: String] => rouille::Response::from_file("application/octet-stream", File::open(format!("elections/{year}/{election_class}/map/{file}")).unwrap()).with_public_cache(u64::max_value()),
-                    (GET) ["/maps/county-map/{file}", file: String] => rouille::Response::from_file("application/octet-stream", File::open(format!("maps/county/{}", file)).unwrap()).with_public_cache(u64::max_value()),
-
-                    (GET) ["/api/election-manifest"] => router::unpack(router::election_manifest()),
-                    (GET) ["/api/election-categories/{election_id}", election_id: usize] => router::unpack(router::election_categories(election_id)),
-                    (GET) ["/api/category-offices/{category_id}", category_id: usize] => router::unpack(router::category_offices(category_id)),
-                    (GET) ["/api/state-results/{office_id}", office_id: usize] => router::unpack(router::state_results(office_id)),
-                    (GET) ["/api/county-results/{office_id}/{county_id}", office_id: usize, county_id: usize] => router::unpack(router::county_results(county_id, office_id)),
-                    (GET) ["/api/{election_id}/{office_id}/counties", office_id: usize, election_id: usize] => router::unpack(router::counties(election_id, office_id)),
-                    (GET) ["/api/municipal-results/{office_id}/{municipal_fips}", office_id: usize, municipal_fips: String] => router::unpack(router::municipal_results(office_id, municipal_fips)),
-                    (GET) ["/api/{office_id}/{county_id}/municipalities", office_id: usize, county_id: usize] => router::unpack(router::municipalities(office_id, county_id)),
-                    (GET) ["/api/{office_id}/all_municipalities", office_id: usize] => router::unpack(router::all_municipalities(office_id)),
+                    (GET) ["/"] => rouille::Response::from_file("text/html", File::open(workspace.index_page()).unwrap()),
+                    (GET) ["/elections.db"] => rouille::Response::from_file("application/octet-stream", File::open(&db).unwrap()),
+
+                    (GET) ["/elections/{year}/{election_class}/map/{file}", year: String, election_class: String, file: String] => rouille::Response::from_file("application/octet-stream", File::open(workspace.path(format!("elections/{year}/{election_class}/map/{file}"))).unwrap()).with_public_cache(u64::MAX),
+                    (GET) ["/maps/county-map/{file}", file: String] => rouille::Response::from_file("application/octet-stream", File::open(workspace.county_map().join(file)).unwrap()).with_public_cache(u64::MAX),
+
+                    (GET) ["/api/election-manifest"] => router::unpack(router::election_manifest(&db)),
+                    (GET) ["/api/election-categories/{election_id}", election_id: usize] => router::unpack(router::election_categories(&db, election_id, router::ballot_party(request))),
+                    (GET) ["/api/category-offices/{category_id}", category_id: usize] => router::unpack(router::category_offices(&db, category_id, router::ballot_party(request))),
+                    (GET) ["/api/state-results/{office_id}", office_id: usize] => router::unpack(router::WriteIns::from_request(request).and_then(|write_ins| router::state_results(&db, office_id, write_ins))),
+                    (GET) ["/api/county-results/{office_id}/{county_id}", office_id: usize, county_id: usize] => router::unpack(router::WriteIns::from_request(request).and_then(|write_ins| router::county_results(&db, county_id, office_id, write_ins))),
+                    (GET) ["/api/{election_id}/{office_id}/counties", office_id: usize, election_id: usize] => router::unpack(router::WriteIns::from_request(request).and_then(|write_ins| router::counties(&db, election_id, office_id, write_ins))),
+                    (GET) ["/api/municipal-results/{office_id}/{municipal_fips}", office_id: usize, municipal_fips: String] => router::unpack(router::WriteIns::from_request(request).and_then(|write_ins| router::municipal_results(&db, office_id, municipal_fips, write_ins))),
+                    (GET) ["/api/{office_id}/{county_id}/municipalities", office_id: usize, county_id: usize] => router::unpack(router::WriteIns::from_request(request).and_then(|write_ins| router::municipalities(&db, office_id, county_id, write_ins))),
+                    (GET) ["/api/{office_id}/all_municipalities", office_id: usize] => router::unpack(router::WriteIns::from_request(request).and_then(|write_ins| router::all_municipalities(&db, office_id, write_ins))),
+                    (GET) ["/api/issue-results/{office_id}", office_id: usize] => router::unpack(router::issue_results(&db, office_id)),
+                    (GET) ["/api/state-turnout/{election_id}", election_id: usize] => router::unpack(router::state_turnout(&db, election_id)),
+                    (GET) ["/api/county-turnout/{election_id}", election_id: usize] => router::unpack(router::county_turnout(&db, election_id)),
+                    (GET) ["/api/municipal-turnout/{election_id}", election_id: usize] => router::unpack(router::municipal_turnout(&db, election_id)),
+                    (GET) ["/api/municipal-history/{municipal_fips}", municipal_fips: String] => router::unpack(router::history_limit(request).and_then(|limit| router::municipal_history(&db, municipal_fips, limit))),
+                    (GET) ["/api/municipal-precincts/{election_id}/{municipal_fips}", election_id: usize, municipal_fips: String] => router::unpack(router::municipal_precincts(&db, election_id, municipal_fips)),
+                    (GET) ["/api/precinct-history/{precinct_id}", precinct_id: usize] => router::unpack(router::history_limit(request).and_then(|limit| router::precinct_history(&db, precinct_id, limit))),
 
                     _ => rouille::Response::empty_404()
                 );
 
                 println!("Handling {} took {}ms", request.url().underline(), std::time::SystemTime::now().duration_since(start).unwrap().as_millis());
 
-                return resp;
+                resp
             });
         }
     }
@@ -0,0 +1,339 @@
+use crate::workspace::Workspace;
+use crate::Log;
+use geo::{BooleanOps, BoundingRect, Intersects, MultiPolygon, Rect};
+use std::path::{Path, PathBuf};
+
+pub struct Feature {
+    pub geometry: MultiPolygon<f64>,
+    pub bounds: Option<Rect<f64>>,
+    pub record: dbase::Record,
+}
+
+impl Feature {
+    fn new(geometry: MultiPolygon<f64>, record: dbase::Record) -> Feature {
+        Feature {
+            bounds: geometry.bounding_rect(),
+            geometry,
+            record
+        }
+    }
+
+    pub fn may_intersect(&self, other: &Feature) -> bool {
+        match (self.bounds, other.bounds) {
+            (Some(a), Some(b)) => a.intersects(&b),
+            _ => false
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<String> {
+        field_string(&self.record, name)
+    }
+}
+
+pub struct Municipal {
+    pub geometry: MultiPolygon<f64>,
+    pub name: String,
+    pub fips: String,
+    pub county: String,
+}
+
+/// Resolves `<dir>/<dir>.shp`, the layout every map directory is expected to follow.
+pub fn shapefile_path(dir: &Path) -> Option<PathBuf> {
+    let name = dir.file_name()?;
+    Some(dir.join(name).with_extension("shp"))
+}
+
+pub fn build_municipal_map(workspace: &Workspace, township_path: &str, city_path: &str, county_path: &str, map_path: &str) {
+    use colored::Colorize;
+    use crate::emit;
+
+    let mut paths = Vec::new();
+    for dir in [township_path, city_path, county_path, map_path] {
+        match shapefile_path(&workspace.path(dir)) {
+            Some(path) => paths.push(path),
+            None => return emit(Log::Error(format!("Failed to get filename for path {}", dir.underline())))
+        }
+    }
+
+    let townships = match read_features(&paths[0]) {
+        Ok(features) => features,
+        Err(why) => return emit(Log::Error(why))
+    };
+
+    let cities = match read_features(&paths[1]) {
+        Ok(features) => features,
+        Err(why) => return emit(Log::Error(why))
+    };
+
+    let counties = match read_features(&paths[2]) {
+        Ok(features) => features,
+        Err(why) => return emit(Log::Error(why))
+    };
+
+    // townships overlap the cities they contain, so the cities are cut out of them first
+    let mut merged = Vec::new();
+    progress(format!("Differencing {} townships against {} cities", townships.len(), cities.len()));
+    for township in townships {
+        let mut geometry = township.geometry.clone();
+        for city in cities.iter().filter(|city| city.may_intersect(&township)) {
+            geometry = geometry.difference(&city.geometry);
+        }
+
+        if !geometry.0.is_empty() {
+            merged.push(Feature::new(geometry, township.record));
+        }
+    }
+    println!(" {}", "done".green());
+
+    merged.extend(cities);
+
+    progress("Assigning municipalities to counties");
+    let mut municipals = Vec::new();
+    for feature in merged {
+        let in_county = counties.iter()
+            .filter(|county| county.may_intersect(&feature))
+            .any(|county| county.geometry.intersects(&feature.geometry));
+        if !in_county {
+            continue;
+        }
+
+        let county = feature.field("COUNTY_CD").unwrap_or_default();
+        let (name, fips) = match feature.field("CORPORATIO") {
+            Some(city) => (format!("{} (City)", city), feature.field("FIPS_CITY_")),
+            None => match feature.field("TOWNSHIP_N") {
+                Some(township) if township == "URBAN" => continue,
+                Some(township) => (format!("{} (Township)", township), feature.field("FIPS_CODE")),
+                None => {
+                    println!();
+                    return emit(Log::Error(format!("Feature has neither {} nor {} set: {:#?}", "CORPORATIO".underline(), "TOWNSHIP_N".underline(), feature.record)));
+                }
+            }
+        };
+
+        let Some(fips) = fips else {
+            println!();
+            return emit(Log::Error(format!("Missing FIPS code for {}", name.underline())));
+        };
+
+        municipals.push(Municipal {
+            geometry: feature.geometry,
+            name,
+            fips,
+            county
+        });
+    }
+    println!(" {}", "done".green());
+
+    if let Err(why) = std::fs::create_dir_all(workspace.path(map_path)) {
+        return emit(Log::Error(format!("Failed to create directory {}: {}", map_path.underline(), why.to_string().underline())));
+    }
+
+    if let Err(why) = write_municipals(&paths[3], &municipals, Some(&paths[0])) {
+        return emit(Log::Error(why));
+    }
+
+    println!("{} Successfully wrote and saved {} ({} municipalities)", "Finished!".green().bold(), paths[3].display().to_string().underline(), municipals.len());
+}
+
+/// Drops the municipalities listed in `map-filter.temp` and dissolves each `a,b,c=a` group in
+/// `map-merge.temp` into a single feature carrying the combined FIPS string used in the database.
+pub fn finalize_map(workspace: &Workspace, election_path: &str, map_path: &str) {
+    use colored::Colorize;
+    use std::collections::{HashMap, HashSet};
+    use crate::emit;
+
+    let Some(municipals_path) = shapefile_path(&workspace.path(map_path)) else {
+        return emit(Log::Error(format!("Failed to get filename for path {}", map_path.underline())));
+    };
+
+    let filter = match std::fs::read_to_string(workspace.filter_file()) {
+        Ok(contents) => contents.lines().map(|line| line.trim().to_string()).filter(|line| !line.is_empty()).collect::<HashSet<_>>(),
+        Err(why) => {
+            emit(Log::Error(format!("unable to open {}: {}", workspace.filter_file().display().to_string().underline(), why.to_string().underline())));
+            return emit(Log::Info(format!("run the {} module first", "import-election".underline())));
+        }
+    };
+
+    let merges = match std::fs::read_to_string(workspace.merge_file()) {
+        Ok(contents) => {
+            let mut merges: Vec<Vec<String>> = Vec::new();
+            for line in contents.lines().map(str::trim).filter(|line| !line.is_empty()) {
+                let Some((members, _)) = line.split_once('=') else {
+                    return emit(Log::Error(format!("Malformed line in {}: {}", "map-merge.temp".underline(), line.underline())));
+                };
+
+                let members: Vec<String> = members.split(',').map(|code| code.trim().to_string()).collect();
+                if !merges.contains(&members) {
+                    merges.push(members);
+                }
+            }
+
+            merges
+        },
+        Err(why) => {
+            emit(Log::Error(format!("unable to open {}: {}", workspace.merge_file().display().to_string().underline(), why.to_string().underline())));
+            return emit(Log::Info(format!("run the {} module first", "import-election".underline())));
+        }
+    };
+
+    let municipals = match read_municipals(&municipals_path) {
+        Ok(municipals) => municipals,
+        Err(why) => return emit(Log::Error(why))
+    };
+
+    let mut groups: HashMap<&str, usize> = HashMap::new(); // fips -> index into merges
+    for (idx, members) in merges.iter().enumerate() {
+        for code in members {
+            groups.insert(code.as_str(), idx);
+        }
+    }
+
+    progress(format!("Finalizing {} municipalities ({} filtered, {} merged groups)", municipals.len(), filter.len(), merges.len()));
+    let mut finalized = Vec::new();
+    let mut grouped: Vec<Vec<Municipal>> = merges.iter().map(|_| Vec::new()).collect();
+    for municipal in municipals {
+        if filter.contains(&municipal.fips) {
+            continue;
+        }
+
+        match groups.get(municipal.fips.as_str()) {
+            Some(idx) => grouped[*idx].push(municipal),
+            None => finalized.push(municipal)
+        }
+    }
+
+    for (members, mut parts) in merges.iter().zip(grouped) {
+        if parts.is_empty() {
+            println!();
+            return emit(Log::Error(format!("None of the municipalities {} exist in {}", members.join(",").underline(), municipals_path.display().to_string().underline())));
+        }
+
+        for code in members.iter().filter(|code| !parts.iter().any(|part| &part.fips == *code)) {
+            println!();
+            emit(Log::Warning(format!("{} is merged but missing from {}", code.underline(), municipals_path.display().to_string().underline())));
+        }
+
+        parts.sort_by_key(|part| members.iter().position(|code| *code == part.fips));
+        finalized.push(Municipal {
+            geometry: geo::unary_union(parts.iter().map(|part| &part.geometry)),
+            name: parts.iter().map(|part| part.name.as_str()).collect::<Vec<_>>().join(" / "),
+            fips: members.join(","),
+            county: parts[0].county.clone()
+        });
+    }
+    println!(" {}", "done".green());
+
+    let output_dir = workspace.path(election_path).join("map");
+    if let Err(why) = std::fs::create_dir_all(&output_dir) {
+        return emit(Log::Error(format!("Failed to create directory {}: {}", output_dir.display().to_string().underline(), why.to_string().underline())));
+    }
+
+    let output_path = output_dir.join("map.shp");
+    if let Err(why) = write_municipals(&output_path, &finalized, Some(&municipals_path)) {
+        return emit(Log::Error(why));
+    }
+
+    println!("{} Successfully wrote and saved {} ({} municipalities)", "Finished!".green().bold(), output_path.display().to_string().underline(), finalized.len());
+}
+
+pub fn read_municipals(path: &Path) -> Result<Vec<Municipal>, String> {
+    use colored::Colorize;
+
+    let mut municipals = Vec::new();
+    for feature in read_features(path)? {
+        match (feature.field("name"), feature.field("fips"), feature.field("county")) {
+            (Some(name), Some(fips), Some(county)) => municipals.push(Municipal {
+                geometry: feature.geometry,
+                name,
+                fips,
+                county
+            }),
+            _ => return Err(format!("Feature in {} is missing name, fips, or county: {:#?}", path.display().to_string().underline(), feature.record))
+        }
+    }
+
+    Ok(municipals)
+}
+
+/// Writes the `name`/`fips`/`county` layer consumed by extract-municipal-codes and the viewer.
+/// When `prj_source` is given its projection file is copied alongside so the output keeps the same CRS.
+pub fn write_municipals(path: &Path, municipals: &[Municipal], prj_source: Option<&Path>) -> Result<(), String> {
+    use colored::Colorize;
+    use dbase::{FieldValue, Record, TableWriterBuilder};
+
+    let table = TableWriterBuilder::new()
+        .add_character_field("name".try_into().unwrap(), 128)
+        .add_character_field("fips".try_into().unwrap(), 254)
+        .add_character_field("county".try_into().unwrap(), 16);
+
+    let mut writer = shapefile::Writer::from_path(path, table).map_err(|why| format!("Failed to create {}: {}", path.display().to_string().underline(), why.to_string().underline()))?;
+    for municipal in municipals {
+        let mut record = Record::default();
+        record.insert("name".to_string(), FieldValue::Character(Some(municipal.name.clone())));
+        record.insert("fips".to_string(), FieldValue::Character(Some(municipal.fips.clone())));
+        record.insert("county".to_string(), FieldValue::Character(Some(municipal.county.clone())));
+
+        let shape = shapefile::Polygon::from(municipal.geometry.clone());
+        writer.write_shape_and_record(&shape, &record).map_err(|why| format!("Failed to write {}: {}", municipal.name.underline(), why.to_string().underline()))?;
+    }
+
+    if let Some(prj) = prj_source.map(|source| source.with_extension("prj")).filter(|prj| prj.exists()) {
+        std::fs::copy(&prj, path.with_extension("prj")).map_err(|why| format!("Failed to copy {}: {}", prj.display().to_string().underline(), why.to_string().underline()))?;
+    }
+
+    Ok(())
+}
+
+pub fn read_features(path: &Path) -> Result<Vec<Feature>, String> {
+    use colored::Colorize;
+    use geo::Geometry;
+
+    progress(format!("Reading shapefile {}", path.display().to_string().underline()));
+    let mut reader = shapefile::Reader::from_path(path).map_err(|why| {
+        println!();
+        format!("Failed to open shapefile {}: {}", path.display().to_string().underline(), why.to_string().underline())
+    })?;
+
+    let mut features = Vec::new();
+    for shape_record in reader.iter_shapes_and_records() {
+        let (shape, record) = shape_record.map_err(|why| {
+            println!();
+            why.to_string()
+        })?;
+
+        let geometry = match Geometry::<f64>::try_from(shape) {
+            Ok(Geometry::MultiPolygon(geometry)) => geometry,
+            Ok(Geometry::Polygon(geometry)) => MultiPolygon::new(vec![geometry]),
+            _ => {
+                println!();
+                return Err(format!("{} must only contain polygons: {:#?}", path.display().to_string().underline(), record));
+            }
+        };
+
+        features.push(Feature::new(geometry, record));
+    }
+
+    println!(" {}", "done".green());
+    Ok(features)
+}
+
+fn field_string(record: &dbase::Record, name: &str) -> Option<String> {
+    use dbase::FieldValue;
+
+    let value = match record.get(name)? {
+        FieldValue::Character(Some(s)) | FieldValue::Memo(s) => s.trim().to_string(),
+        FieldValue::Numeric(Some(n)) | FieldValue::Double(n) => format!("{}", n),
+        FieldValue::Float(Some(n)) => format!("{}", n),
+        FieldValue::Integer(n) => n.to_string(),
+        _ => return None
+    };
+
+    if value.is_empty() { None } else { Some(value) }
+}
+
+fn progress<S: Into<String>>(s: S) {
+    use std::io::Write;
+
+    print!("{}", s.into());
+    std::io::stdout().flush().expect("Unable to flush stdout.");
+}
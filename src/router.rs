@@ -1,9 +1,12 @@
+use std::path::Path;
+
 #[derive(serde::Serialize)]
 pub struct Election {
 	name: String,
 	date: chrono::NaiveDate,
 	map_path: String,
-	id: usize
+	id: usize,
+	kind: String // general, primary or special
 }
 
 #[derive(serde::Serialize)]
@@ -12,6 +15,22 @@ pub struct OfficeCategory {
 	id: usize
 }
 
+#[derive(serde::Serialize)]
+pub struct Category {
+	name: String,
+	id: usize,
+	ballot_party: Option<String> // the party ballot of a primary, if the whole category is on one
+}
+
+#[derive(serde::Serialize)]
+pub struct Office {
+	name: String,
+	id: usize,
+	kind: String, // "candidate" or "issue"
+	seats: usize,
+	ballot_party: Option<String>
+}
+
 #[derive(serde::Serialize)]
 pub struct MunicipalHold {
 	name: String,
@@ -21,19 +40,117 @@ pub struct MunicipalHold {
 #[derive(serde::Serialize, Debug, Clone)]
 pub struct ElectionResult {
 	name: String,
-	votes: usize
+	party: Option<String>,
+	votes: usize,
+	write_in: bool,
+	ballot_share: f64, // share of the ballots cast for the office, each of which may vote for several seats
+	share: f64 // the share to show: of the votes for a single seat and of the ballots for several
 }
 
 #[derive(serde::Serialize, Debug)]
 pub struct SumElectionResult {
 	total_votes: usize,
+	ballots: usize,
+	ballots_estimated: bool, // whether ballots is derived from the votes, as some precincts have no turnout
+	seats: usize,
+	winners: Vec<String>,
 	candidates: Vec<ElectionResult>
 }
 
+impl SumElectionResult {
+	/// Totals the candidates after applying `write_ins`; they are expected in descending order of votes.
+	/// Shares are of `ballots_cast` in the precincts that voted on the office. Without it, ballots are
+	/// estimated as the total votes over the number of seats, as each voter may choose up to one
+	/// candidate per seat, which overstates shares where voters left seats blank. Either way there are
+	/// never fewer ballots than votes for the leading candidate. Single-seat contests are shown by their
+	/// share of the votes, which is what is certified and leaves out ballots that skipped the office.
+	fn new(mut candidates: Vec<ElectionResult>, write_ins: WriteIns, rules: OfficeRules, ballots_cast: Option<usize>) -> SumElectionResult {
+		let seats = rules.seats;
+		match write_ins {
+			WriteIns::Include => {},
+			WriteIns::Exclude => candidates.retain(|c| !c.write_in),
+			WriteIns::Collapse => if candidates.iter().any(|c| c.write_in) {
+				let votes = candidates.iter().filter(|c| c.write_in).map(|c| c.votes).sum();
+				candidates.retain(|c| !c.write_in);
+				candidates.push(ElectionResult {
+					name: "Write-ins".to_string(),
+					party: None,
+					votes,
+					write_in: true,
+					ballot_share: 0.0,
+					share: 0.0
+				});
+				candidates.sort_by_key(|c| std::cmp::Reverse(c.votes));
+			}
+		}
+
+		let total_votes: usize = candidates.iter().map(|c| c.votes).sum();
+		let leading = candidates.iter().map(|c| c.votes).max().unwrap_or(0);
+		let ballots_estimated = ballots_cast.is_none();
+		let ballots = ballots_cast.unwrap_or(total_votes.div_ceil(seats)).max(leading);
+		for candidate in candidates.iter_mut() {
+			candidate.ballot_share = if ballots == 0 { 0.0 } else { candidate.votes as f64 / ballots as f64 };
+			candidate.share = match seats {
+				1 if total_votes == 0 => 0.0,
+				1 => candidate.votes as f64 / total_votes as f64,
+				_ => candidate.ballot_share
+			};
+		}
+
+		// a ballot issue is won by Yes only if it passes, which may take more than a majority
+		let collapsed = matches!(write_ins, WriteIns::Collapse);
+		let winners = match rules.threshold {
+			Some(threshold) => {
+				let choices: Vec<(String, usize)> = candidates.iter().map(|c| (c.name.clone(), c.votes)).collect();
+				let passed = IssueResult::new(String::new(), &choices, threshold).passed;
+				candidates.iter()
+					.filter(|c| c.votes > 0 && crate::converter::issue_choice(&c.name) == Some(passed))
+					.take(1)
+					.map(|c| c.name.clone())
+					.collect()
+			},
+			None => candidates.iter()
+				.filter(|c| c.votes > 0 && !(collapsed && c.write_in))
+				.take(seats)
+				.map(|c| c.name.clone())
+				.collect()
+		};
+
+		SumElectionResult {
+			total_votes,
+			ballots,
+			ballots_estimated,
+			seats,
+			winners,
+			candidates
+		}
+	}
+}
+
+/// How write-in candidates are reported, from the `write_ins` query parameter.
+#[derive(Debug, Clone, Copy)]
+pub enum WriteIns {
+	Include,
+	Exclude,
+	Collapse // summed into a single "Write-ins" line
+}
+
+impl WriteIns {
+	pub fn from_request(request: &rouille::Request) -> std::result::Result<WriteIns, Error> {
+		match request.get_param("write_ins").as_deref() {
+			None | Some("include") => Ok(WriteIns::Include),
+			Some("exclude") => Ok(WriteIns::Exclude),
+			Some("collapse") => Ok(WriteIns::Collapse),
+			Some(other) => Err(format!("Unknown write_ins={}, expected include, exclude or collapse", other).into())
+		}
+	}
+}
+
 #[derive(serde::Serialize, Debug)]
 pub struct Municipality {
 	name: String,
 	fips: String,
+	on_ballot: bool, // whether any of its precincts voted on the office
 	election: SumElectionResult
 }
 
@@ -41,9 +158,90 @@ pub struct Municipality {
 pub struct County {
 	name: String,
 	id: usize,
+	fips: Option<String>, // the state and county FIPS code, e.g. 39153
+	on_ballot: bool,
 	election: SumElectionResult
 }
 
+#[derive(serde::Serialize, Debug)]
+pub struct Turnout {
+	name: String,
+	registered_voters: usize,
+	ballots_cast: usize,
+	turnout: f64
+}
+
+impl Turnout {
+	fn new(name: String, registered_voters: usize, ballots_cast: usize) -> Turnout {
+		Turnout {
+			name,
+			registered_voters,
+			ballots_cast,
+			turnout: if registered_voters == 0 { 0.0 } else { ballots_cast as f64 / registered_voters as f64 }
+		}
+	}
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct IssueResult {
+	name: String,
+	yes: usize,
+	no: usize,
+	yes_share: f64,
+	margin: i64, // yes votes less no votes
+	passed: bool
+}
+
+impl IssueResult {
+	/// An issue passes with more Yes than No votes and at least `threshold` of them.
+	fn new(name: String, choices: &[(String, usize)], threshold: f64) -> IssueResult {
+		let side = |yes: bool| choices.iter().filter(|(choice, _)| crate::converter::issue_choice(choice) == Some(yes)).map(|(_, votes)| votes).sum::<usize>();
+		let (yes, no) = (side(true), side(false));
+		let yes_share = if yes + no == 0 { 0.0 } else { yes as f64 / (yes + no) as f64 };
+
+		IssueResult {
+			name,
+			yes,
+			no,
+			yes_share,
+			margin: yes as i64 - no as i64,
+			passed: yes > no && yes_share >= threshold
+		}
+	}
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct IssueResults {
+	threshold: f64,
+	state: IssueResult,
+	counties: std::collections::HashMap<String, IssueResult>, // by county name
+	municipalities: std::collections::HashMap<String, IssueResult> // by FIPS code
+}
+
+#[derive(serde::Serialize)]
+pub struct MunicipalHistory {
+	election: Election,
+	fips: String, // every code of the municipality that election, which may have been merged
+	turnout: Turnout
+}
+
+#[derive(serde::Serialize)]
+pub struct PrecinctHistory {
+	election: Election,
+	id: usize,
+	county: String,
+	municipality: String,
+	fips: String,
+	method: Option<String>, // how it was linked to the previous election: name, alias or manual
+	turnout: Turnout
+}
+
+#[derive(serde::Serialize)]
+pub struct Precinct {
+	name: String,
+	id: usize
+}
+
 #[derive(serde::Serialize, Debug)]
 pub struct Error {
 	error: String
@@ -75,8 +273,8 @@ impl From<std::num::ParseIntError> for Error {
 
 type Result = std::result::Result<rouille::Response, Error>;
 
-pub fn election_manifest() -> Result {
-	let mut conn = rusqlite::Connection::open("./elections.db")?;
+pub fn election_manifest(db: &Path) -> Result {
+	let mut conn = rusqlite::Connection::open(db)?;
 	let conn = conn.savepoint()?;
 
 	let mut stmt = conn.prepare("SELECT * FROM election_info ORDER BY date DESC")?;
@@ -85,7 +283,8 @@ pub fn election_manifest() -> Result {
 			name: row.get(1)?,
 			date: row.get(2)?,
 			map_path: row.get(3)?,
-			id: row.get(0)?
+			id: row.get(0)?,
+			kind: row.get(4)?
 		})
 	})?.collect::<Vec<std::result::Result<Election, rusqlite::Error>>>();
 
@@ -100,17 +299,31 @@ pub fn election_manifest() -> Result {
 	Ok(rouille::Response::json(&elections))
 }
 
-pub fn election_categories(id: usize) -> Result {
-	let mut conn = rusqlite::Connection::open("./elections.db")?;
+/// The `party` query parameter: `None` when absent, `Some(None)` for `nonpartisan` and otherwise a party code like `D`
+/// (abbreviations such as `GRN` are read as their code).
+pub fn ballot_party(request: &rouille::Request) -> Option<Option<String>> {
+	request.get_param("party").map(|party| match party.to_lowercase().as_str() {
+		"nonpartisan" => None,
+		_ => Some(crate::converter::party_code(&party).map(str::to_string).unwrap_or_else(|| party.to_uppercase()))
+	})
+}
+
+/// Categories are filtered by their own party ballot or that of any of their offices.
+pub fn election_categories(db: &Path, id: usize, ballot_party: Option<Option<String>>) -> Result {
+	let mut conn = rusqlite::Connection::open(db)?;
 	let conn = conn.savepoint()?;
 
-	let mut stmt = conn.prepare("SELECT * FROM office_category WHERE electionId=?1")?;
-	let result_vec = stmt.query_map([id], |row| {
-		Ok(OfficeCategory {
+	let mut stmt = conn.prepare("
+		SELECT oc.id, oc.name, oc.ballot_party FROM office_category oc WHERE oc.electionId=?1
+		AND (?2 = 0 OR oc.ballot_party IS ?3 OR EXISTS (SELECT 1 FROM office_election o WHERE o.categoryId = oc.id AND o.ballot_party IS ?3))
+	")?;
+	let result_vec = stmt.query_map(rusqlite::params![id, ballot_party.is_some(), ballot_party.flatten()], |row| {
+		Ok(Category {
 			name: row.get(1)?,
-			id: row.get(0)?
+			id: row.get(0)?,
+			ballot_party: row.get(2)?
 		})
-	})?.collect::<Vec<std::result::Result<OfficeCategory, rusqlite::Error>>>();
+	})?.collect::<Vec<std::result::Result<Category, rusqlite::Error>>>();
 
 	let mut categories = Vec::new();
 	for category in result_vec.iter() {
@@ -123,17 +336,20 @@ pub fn election_categories(id: usize) -> Result {
 	Ok(rouille::Response::json(&categories))
 }
 
-pub fn category_offices(id: usize) -> Result {
-	let mut conn = rusqlite::Connection::open("./elections.db")?;
+pub fn category_offices(db: &Path, id: usize, ballot_party: Option<Option<String>>) -> Result {
+	let mut conn = rusqlite::Connection::open(db)?;
 	let conn = conn.savepoint()?;
 
-	let mut stmt = conn.prepare("SELECT * FROM office_election WHERE categoryId=?1")?;
-	let result_vec = stmt.query_map([id], |row| {
-		Ok(OfficeCategory {
+	let mut stmt = conn.prepare("SELECT id, name, kind, seats, ballot_party FROM office_election WHERE categoryId=?1 AND (?2 = 0 OR ballot_party IS ?3)")?;
+	let result_vec = stmt.query_map(rusqlite::params![id, ballot_party.is_some(), ballot_party.flatten()], |row| {
+		Ok(Office {
 			name: row.get(1)?,
-			id: row.get(0)?
+			id: row.get(0)?,
+			kind: row.get(2)?,
+			seats: row.get(3)?,
+			ballot_party: row.get(4)?
 		})
-	})?.collect::<Vec<std::result::Result<OfficeCategory, rusqlite::Error>>>();
+	})?.collect::<Vec<std::result::Result<Office, rusqlite::Error>>>();
 
 	let mut categories = Vec::new();
 	for category in result_vec.iter() {
@@ -146,27 +362,19 @@ pub fn category_offices(id: usize) -> Result {
 	Ok(rouille::Response::json(&categories))
 }
 
-pub fn state_results(id: usize) -> Result {
-	let mut conn = rusqlite::Connection::open("./elections.db")?;
+pub fn state_results(db: &Path, id: usize, write_ins: WriteIns) -> Result {
+	let mut conn = rusqlite::Connection::open(db)?;
 	let conn = conn.savepoint()?;
 
-	let mut stmt = conn.prepare("SELECT sum(votes) as totalVotes from indexed_state_results WHERE officeId=?1")?;
-	let results_vec = stmt.query_map([id], |row| {
-		row.get(0)
-	})?.collect::<Vec<std::result::Result<usize, rusqlite::Error>>>();
-	if results_vec.len() > 1 {
-		return Err(Error {
-			error: format!("Expected 1 row, got {}", results_vec.len())
-		});
-	}
-
-	let Ok(total_votes) = &results_vec[0] else { return Err(Error { error: format!("Failed to get total_votes for officeId={}: {:#?}", id, results_vec) }) };
-
 	let mut stmt = conn.prepare("SELECT * FROM indexed_state_results WHERE officeId=?1 ORDER BY votes DESC")?;
 	let results_vec = stmt.query_map([id], |row| {
 		Ok(ElectionResult {
 			votes: row.get(1)?,
-			name: row.get(3)?
+			name: row.get(3)?,
+			party: row.get(4)?,
+			write_in: row.get(5)?,
+			ballot_share: 0.0,
+			share: 0.0
 		})
 	})?.collect::<Vec<std::result::Result<ElectionResult, rusqlite::Error>>>();
 
@@ -178,14 +386,16 @@ pub fn state_results(id: usize) -> Result {
 		}
 	}
 
-	Ok(rouille::Response::json(&SumElectionResult {
-		total_votes: *total_votes,
-		candidates: res
-	}))
+	if res.is_empty() {
+		return Err(Error { error: format!("Failed to get total_votes for officeId={}", id) });
+	}
+
+	Ok(rouille::Response::json(&SumElectionResult::new(res, write_ins, office_rules(&conn, id)?, ballots_cast(&conn, id, "", &[])?)))
 }
 
-fn county_results_query(county_id: usize, office_id: usize) -> std::result::Result<SumElectionResult, Error> {
-	let mut conn = rusqlite::Connection::open("./elections.db")?;
+/// Returns None when no precinct of the county voted on the office.
+fn county_results_query(db: &Path, county_id: usize, office_id: usize, write_ins: WriteIns, ballots_cast: Option<usize>) -> std::result::Result<Option<SumElectionResult>, Error> {
+	let mut conn = rusqlite::Connection::open(db)?;
 	let conn = conn.savepoint()?;
 
 	let mut stmt = conn.prepare("SELECT name FROM county WHERE id=?1")?;
@@ -198,25 +408,17 @@ fn county_results_query(county_id: usize, office_id: usize) -> std::result::Resu
 		});
 	}
 
-	let Ok(name) = &results_vec[0] else { return Err(Error { error: format!("Failed to get county name") }) };
+	let Ok(name) = &results_vec[0] else { return Err(Error { error: "Failed to get county name".to_string() }) };
 
-	let mut stmt = conn.prepare("SELECT sum(votes) as totalVotes from indexed_county_results WHERE officeId=?1 AND countyName=?2")?;
-	let results_vec = stmt.query_map([&office_id.to_string(), name], |row| {
-		row.get(0)
-	})?.collect::<Vec<std::result::Result<usize, rusqlite::Error>>>();
-	if results_vec.len() > 1 {
-		return Err(Error {
-			error: format!("Expected 1 row, got {}", results_vec.len())
-		});
-	}
-
-	let Ok(total_votes) = &results_vec[0] else { return Err(Error { error: format!("Failed to get total_votes for countyId={} and officeId={}: {:?}", county_id, office_id, results_vec) }) };
-
-	let mut stmt = conn.prepare("SELECT candidateName, votes FROM indexed_county_results WHERE countyName=?1 and officeId=?2 ORDER BY votes DESC")?;
+	let mut stmt = conn.prepare("SELECT candidateName, votes, candidateParty, isWriteIn FROM indexed_county_results WHERE countyName=?1 and officeId=?2 ORDER BY votes DESC")?;
 	let results_vec = stmt.query_map([name, &office_id.to_string()], |row| {
 		Ok(ElectionResult {
 			name: row.get(0)?,
-			votes: row.get(1)?
+			votes: row.get(1)?,
+			party: row.get(2)?,
+			write_in: row.get(3)?,
+			ballot_share: 0.0,
+			share: 0.0
 		})
 	})?.collect::<Vec<std::result::Result<ElectionResult, rusqlite::Error>>>();
 
@@ -228,77 +430,131 @@ fn county_results_query(county_id: usize, office_id: usize) -> std::result::Resu
 		}
 	}
 
-	Ok(SumElectionResult {
-		total_votes: *total_votes,
-		candidates: res
-	})
+	if res.is_empty() {
+		return Ok(None);
+	}
+
+	Ok(Some(SumElectionResult::new(res, write_ins, office_rules(&conn, office_id)?, ballots_cast)))
 }
 
-pub fn county_results(county_id: usize, office_id: usize) -> Result {
-	Ok(rouille::Response::json(&county_results_query(county_id, office_id)?))
+pub fn county_results(db: &Path, county_id: usize, office_id: usize, write_ins: WriteIns) -> Result {
+	let conn = rusqlite::Connection::open(db)?;
+	let ballots = ballots_cast(&conn, office_id, "AND p.countyId=?2", &[&county_id])?;
+	match county_results_query(db, county_id, office_id, write_ins, ballots)? {
+		Some(election) => Ok(rouille::Response::json(&election)),
+		None => Err(Error { error: format!("Failed to get total_votes for countyId={} and officeId={}", county_id, office_id) })
+	}
 }
 
-pub fn counties(election_id: usize, office_id: usize) -> Result {
-	let mut conn = rusqlite::Connection::open("./elections.db")?;
+pub fn counties(db: &Path, election_id: usize, office_id: usize, write_ins: WriteIns) -> Result {
+	let mut conn = rusqlite::Connection::open(db)?;
 	let conn = conn.savepoint()?;
 
-	let mut stmt = conn.prepare("SELECT name, id FROM county WHERE electionId=?1")?;
+	let ballots = ballots_cast_by::<usize>(&conn, office_id, "p.countyId")?;
+	let mut stmt = conn.prepare("SELECT name, id, fips FROM county WHERE electionId=?1")?;
 	let results_vec = stmt.query_map([election_id], |row| {
-		Ok(OfficeCategory {
+		Ok((OfficeCategory {
 			name: row.get(0)?,
 			id: row.get(1)?
-		})
-	})?.collect::<Vec<std::result::Result<OfficeCategory, rusqlite::Error>>>();
+		}, row.get::<_, Option<String>>(2)?))
+	})?.collect::<Vec<std::result::Result<(OfficeCategory, Option<String>), rusqlite::Error>>>();
 
 	let mut res: std::collections::HashMap<String, County> = std::collections::HashMap::new();
 	for ele in results_vec.iter() {
 		match ele {
-			Ok(ele) => res.insert(ele.name.clone(), County {
-				name: ele.name.clone(),
-				id: ele.id,
-				election: match county_results_query(ele.id, office_id) {
-					Ok(e) => e,
-					Err(why) => continue
-				}
-			}),
-			Err(why) => None // skip
+			Ok((ele, fips)) => {
+				let (on_ballot, election) = match county_results_query(db, ele.id, office_id, write_ins, ballots.get(&ele.id).copied().flatten())? {
+					Some(e) => (true, e),
+					None => (false, SumElectionResult::new(Vec::new(), write_ins, OfficeRules { seats: 1, threshold: None }, None)) // no precinct in the county voted on this office
+				};
+
+				res.insert(ele.name.clone(), County {
+					name: ele.name.clone(),
+					id: ele.id,
+					fips: fips.clone(),
+					on_ballot,
+					election
+				})
+			},
+			Err(_) => None // skip
 		};
 	}
 
 	Ok(rouille::Response::json(&res))
 }
 
-fn municipal_results_query(office_id: usize, municipal_fips: &str, conn: &rusqlite::Connection) -> std::result::Result<SumElectionResult, Error> {
-    let mut stmt = conn.prepare("SELECT sum(votes) FROM indexed_municipal_results WHERE officeId=?1 AND municipalCode=?2")?;
-    let total_votes: usize = stmt.query_row(rusqlite::params![office_id, municipal_fips], |row| row.get(0))?;
-    
-    let mut stmt = conn.prepare("SELECT candidateName, votes FROM indexed_municipal_results WHERE officeId=?1 AND municipalCode=?2 ORDER BY votes DESC")?;
+fn municipal_results_query(office_id: usize, municipal_fips: &str, write_ins: WriteIns, conn: &rusqlite::Connection) -> std::result::Result<SumElectionResult, Error> {
+    let mut stmt = conn.prepare("SELECT candidateName, votes, candidateParty, isWriteIn FROM indexed_municipal_results WHERE officeId=?1 AND municipalCode=?2 ORDER BY votes DESC")?;
     let results_vec = stmt.query_map(rusqlite::params![office_id, municipal_fips], |row| {
         Ok(ElectionResult {
             name: row.get(0)?,
             votes: row.get(1)?,
+            party: row.get(2)?,
+            write_in: row.get(3)?,
+            ballot_share: 0.0,
+            share: 0.0,
         })
     })?.collect::<SqlResult<Vec<ElectionResult>>>()?;
 
-    Ok(SumElectionResult {
-        total_votes,
-        candidates: results_vec,
-    })
+    if results_vec.is_empty() {
+        return Err(format!("No results for officeId={} in municipalCode={}", office_id, municipal_fips).into());
+    }
+
+    Ok(SumElectionResult::new(results_vec, write_ins, office_rules(conn, office_id)?, ballots_cast(conn, office_id, "AND p.municipalId IN (SELECT id FROM municipality WHERE fips=?2)", &[&municipal_fips])?))
 }
 
-pub fn municipal_results(office_id: usize, municipal_fips: String) -> std::result::Result<rouille::Response, Error> {
-    let conn = rusqlite::Connection::open("./elections.db")?;
-    let result = municipal_results_query(office_id, &municipal_fips, &conn)?;
+pub fn municipal_results(db: &Path, office_id: usize, municipal_fips: String, write_ins: WriteIns) -> std::result::Result<rouille::Response, Error> {
+    let conn = rusqlite::Connection::open(db)?;
+    let result = municipal_results_query(office_id, &municipal_fips, write_ins, &conn)?;
     Ok(rouille::Response::json(&result))
 }
 
 type SqlResult<T> = std::result::Result<T, rusqlite::Error>;
 
-pub fn all_municipalities(office_id: usize) -> Result {
-	let conn = rusqlite::Connection::open("./elections.db")?;
+/// The ballots cast in the precincts `p` that voted on the office, or nothing if any of them has no
+/// turnout. `filter` narrows the precincts further, with `params` bound from `?2`.
+fn ballots_cast(conn: &rusqlite::Connection, office_id: usize, filter: &str, params: &[&dyn rusqlite::ToSql]) -> SqlResult<Option<usize>> {
+	let sql = format!("SELECT {} FROM (SELECT {} AS ballots FROM precinct p JOIN office_election o ON o.id=?1 WHERE p.id IN (SELECT precinctId FROM indexed_precinct_results WHERE officeId=?1) {})", BALLOTS_CAST, PRECINCT_BALLOTS, filter);
+	let params: Vec<&dyn rusqlite::ToSql> = std::iter::once(&office_id as &dyn rusqlite::ToSql).chain(params.iter().copied()).collect();
+	conn.query_row(&sql, params.as_slice(), |row| row.get(0))
+}
+
+/// `ballots_cast` for each `group` of the precincts `p`, or municipalities `m`, that voted on the office.
+fn ballots_cast_by<K: rusqlite::types::FromSql + std::hash::Hash + Eq>(conn: &rusqlite::Connection, office_id: usize, group: &str) -> SqlResult<std::collections::HashMap<K, Option<usize>>> {
+	let sql = format!("SELECT grouped, {} FROM (SELECT {} AS grouped, {} AS ballots FROM precinct p LEFT JOIN municipality m ON p.municipalId = m.id JOIN office_election o ON o.id=?1 WHERE p.id IN (SELECT precinctId FROM indexed_precinct_results WHERE officeId=?1)) GROUP BY grouped", BALLOTS_CAST, group, PRECINCT_BALLOTS);
+	conn.prepare(&sql)?.query_map([office_id], |row| Ok((row.get(0)?, row.get(1)?)))?.collect()
+}
+
+/// The ballots a precinct `p` cast for office `o`. In a primary, `ballotsCast` counts every party's
+/// ballots, so contests on one party's ballot take that party's alone and have none if it was not read.
+const PRECINCT_BALLOTS: &str = "CASE WHEN o.ballot_party IS NULL THEN p.ballotsCast ELSE (SELECT pb.ballotsCast FROM precinct_party_ballots pb WHERE pb.precinctId = p.id AND pb.party = o.ballot_party) END";
+
+/// The sum of the `ballots` of each precinct, unless some have none recorded.
+const BALLOTS_CAST: &str = "CASE WHEN count(ballots) = count(*) THEN sum(ballots) END";
+
+/// What decides the winners of an office: the seats it fills, and for a ballot issue the share of
+/// Yes votes it needs to pass.
+#[derive(Debug, Clone, Copy)]
+struct OfficeRules {
+	seats: usize,
+	threshold: Option<f64> // only ballot issues have one
+}
+
+fn office_rules(conn: &rusqlite::Connection, office_id: usize) -> SqlResult<OfficeRules> {
+	conn.query_row("SELECT seats, kind, threshold FROM office_election WHERE id=?1", [office_id], |row| {
+		let kind: String = row.get(1)?;
+		Ok(OfficeRules {
+			seats: row.get(0)?,
+			threshold: if kind == "issue" { Some(row.get::<_, Option<f64>>(2)?.unwrap_or(0.5)) } else { None }
+		})
+	})
+}
+
+pub fn all_municipalities(db: &Path, office_id: usize, write_ins: WriteIns) -> Result {
+	let conn = rusqlite::Connection::open(db)?;
     
-    let mut stmt = conn.prepare("SELECT m.name, m.fips FROM municipality m JOIN precinct p on m.id = p.municipalId")?;
-    let results_vec = stmt.query_map(rusqlite::params![], |row| {
+    let mut stmt = conn.prepare("SELECT m.name, m.fips FROM municipality m JOIN precinct p on m.id = p.municipalId WHERE m.electionId = (SELECT oc.electionId FROM office_election o JOIN office_category oc ON o.categoryId = oc.id WHERE o.id=?1)")?;
+    let results_vec = stmt.query_map(rusqlite::params![office_id], |row| {
         Ok(MunicipalHold {
             name: row.get(0)?,
             fips: row.get(1)?,
@@ -310,12 +566,12 @@ pub fn all_municipalities(office_id: usize) -> Result {
     // Query all results in batch
     let query_placeholders = municipal_fips_list.iter().map(|_| "?").collect::<Vec<_>>().join(",");
     let query = format!(
-        "SELECT municipalCode, candidateName, votes, officeId FROM indexed_municipal_results WHERE officeId=?1 AND municipalCode IN ({}) ORDER BY votes DESC",
+        "SELECT municipalCode, candidateName, votes, candidateParty, isWriteIn FROM indexed_municipal_results WHERE officeId=?1 AND municipalCode IN ({}) ORDER BY votes DESC",
         query_placeholders
     );
     
     let mut stmt = conn.prepare(&query)?;
-    let params: Vec<&dyn rusqlite::ToSql> = vec![&office_id as &dyn rusqlite::ToSql].into_iter().chain(municipal_fips_list.iter().map(|fips| &*fips as &dyn rusqlite::ToSql)).collect();
+    let params: Vec<&dyn rusqlite::ToSql> = vec![&office_id as &dyn rusqlite::ToSql].into_iter().chain(municipal_fips_list.iter().map(|fips| fips as &dyn rusqlite::ToSql)).collect();
     let mut results_map: std::collections::HashMap<String, Vec<ElectionResult>> = std::collections::HashMap::new();
     
     let mut rows = stmt.query(params.as_slice())?;
@@ -323,38 +579,42 @@ pub fn all_municipalities(office_id: usize) -> Result {
         let municipal_code: String = row.get(0)?;
         let candidate_name: String = row.get(1)?;
         let votes: usize = row.get(2)?;
+        let party: Option<String> = row.get(3)?;
+        let write_in: bool = row.get(4)?;
         
         let result = ElectionResult {
             name: candidate_name,
+            party,
             votes,
+            write_in,
+            ballot_share: 0.0,
+            share: 0.0,
         };
         
-        results_map.entry(municipal_code).or_insert_with(Vec::new).push(result);
+        results_map.entry(municipal_code).or_default().push(result);
     }
     
+    let rules = office_rules(&conn, office_id)?;
+    let ballots = ballots_cast_by::<String>(&conn, office_id, "m.fips")?;
     let mut municipalities = std::collections::HashMap::new();
     for m in results_vec.iter() {
-        if let Some(election_results) = results_map.get(&m.fips) {
-            let total_votes: usize = election_results.iter().map(|r| r.votes).sum();
-            municipalities.insert(
-                m.fips.clone(),
-                Municipality {
-                    name: m.name.clone(),
-                    fips: m.fips.clone(),
-                    election: SumElectionResult {
-                        total_votes,
-                        candidates: election_results.clone(),
-                    },
-                },
-            );
-        }
+        let election_results = results_map.get(&m.fips);
+        municipalities.insert(
+            m.fips.clone(),
+            Municipality {
+                name: m.name.clone(),
+                fips: m.fips.clone(),
+                on_ballot: election_results.is_some(),
+                election: SumElectionResult::new(election_results.cloned().unwrap_or_default(), write_ins, rules, ballots.get(&m.fips).copied().flatten()),
+            },
+        );
     }
 
     Ok(rouille::Response::json(&municipalities))
 }
 
-pub fn municipalities(office_id: usize, county_id: usize) -> std::result::Result<rouille::Response, Error> {
-    let conn = rusqlite::Connection::open("./elections.db")?;
+pub fn municipalities(db: &Path, office_id: usize, county_id: usize, write_ins: WriteIns) -> std::result::Result<rouille::Response, Error> {
+    let conn = rusqlite::Connection::open(db)?;
     
     let mut stmt = conn.prepare("SELECT m.name, m.fips FROM municipality m JOIN precinct p on m.id = p.municipalId WHERE p.countyId=?1")?;
     let results_vec = stmt.query_map(rusqlite::params![county_id], |row| {
@@ -369,12 +629,12 @@ pub fn municipalities(office_id: usize, county_id: usize) -> std::result::Result
     // Query all results in batch
     let query_placeholders = municipal_fips_list.iter().map(|_| "?").collect::<Vec<_>>().join(",");
     let query = format!(
-        "SELECT municipalCode, candidateName, votes, officeId FROM indexed_municipal_results WHERE officeId=?1 AND municipalCode IN ({}) ORDER BY votes DESC",
+        "SELECT municipalCode, candidateName, votes, candidateParty, isWriteIn FROM indexed_municipal_results WHERE officeId=?1 AND municipalCode IN ({}) ORDER BY votes DESC",
         query_placeholders
     );
     
     let mut stmt = conn.prepare(&query)?;
-    let params: Vec<&dyn rusqlite::ToSql> = vec![&office_id as &dyn rusqlite::ToSql].into_iter().chain(municipal_fips_list.iter().map(|fips| &*fips as &dyn rusqlite::ToSql)).collect();
+    let params: Vec<&dyn rusqlite::ToSql> = vec![&office_id as &dyn rusqlite::ToSql].into_iter().chain(municipal_fips_list.iter().map(|fips| fips as &dyn rusqlite::ToSql)).collect();
     let mut results_map: std::collections::HashMap<String, Vec<ElectionResult>> = std::collections::HashMap::new();
     
     let mut rows = stmt.query(params.as_slice())?;
@@ -382,39 +642,263 @@ pub fn municipalities(office_id: usize, county_id: usize) -> std::result::Result
         let municipal_code: String = row.get(0)?;
         let candidate_name: String = row.get(1)?;
         let votes: usize = row.get(2)?;
+        let party: Option<String> = row.get(3)?;
+        let write_in: bool = row.get(4)?;
         
         let result = ElectionResult {
             name: candidate_name,
+            party,
             votes,
+            write_in,
+            ballot_share: 0.0,
+            share: 0.0,
         };
         
-        results_map.entry(municipal_code).or_insert_with(Vec::new).push(result);
+        results_map.entry(municipal_code).or_default().push(result);
     }
     
+    let rules = office_rules(&conn, office_id)?;
+    let ballots = ballots_cast_by::<String>(&conn, office_id, "m.fips")?;
     let mut municipalities = std::collections::HashMap::new();
     for m in results_vec.iter() {
-        if let Some(election_results) = results_map.get(&m.fips) {
-            let total_votes: usize = election_results.iter().map(|r| r.votes).sum();
-            municipalities.insert(
-                m.fips.clone(),
-                Municipality {
-                    name: m.name.clone(),
-                    fips: m.fips.clone(),
-                    election: SumElectionResult {
-                        total_votes,
-                        candidates: election_results.clone(),
-                    },
-                },
-            );
-        }
+        let election_results = results_map.get(&m.fips);
+        municipalities.insert(
+            m.fips.clone(),
+            Municipality {
+                name: m.name.clone(),
+                fips: m.fips.clone(),
+                on_ballot: election_results.is_some(),
+                election: SumElectionResult::new(election_results.cloned().unwrap_or_default(), write_ins, rules, ballots.get(&m.fips).copied().flatten()),
+            },
+        );
     }
 
     Ok(rouille::Response::json(&municipalities))
 }
 
+pub fn state_turnout(db: &Path, election_id: usize) -> Result {
+	let conn = rusqlite::Connection::open(db)?;
+
+	let turnout = conn.query_row("SELECT e.name, t.registeredVoters, t.ballotsCast FROM indexed_state_turnout t JOIN election_info e ON t.electionId = e.id WHERE t.electionId=?1", [election_id], |row| {
+		Ok(Turnout::new(row.get(0)?, row.get(1)?, row.get(2)?))
+	})?;
+
+	Ok(rouille::Response::json(&turnout))
+}
+
+pub fn county_turnout(db: &Path, election_id: usize) -> Result {
+	let conn = rusqlite::Connection::open(db)?;
+
+	let mut stmt = conn.prepare("SELECT countyName, registeredVoters, ballotsCast FROM indexed_county_turnout WHERE electionId=?1")?;
+	let counties = stmt.query_map([election_id], |row| {
+		Ok(Turnout::new(row.get(0)?, row.get(1)?, row.get(2)?))
+	})?.collect::<SqlResult<Vec<Turnout>>>()?;
+
+	let res: std::collections::HashMap<String, Turnout> = counties.into_iter().map(|t| (t.name.clone(), t)).collect();
+	Ok(rouille::Response::json(&res))
+}
+
+pub fn municipal_turnout(db: &Path, election_id: usize) -> Result {
+	let conn = rusqlite::Connection::open(db)?;
+
+	let mut stmt = conn.prepare("SELECT municipalCode, municipalName, registeredVoters, ballotsCast FROM indexed_municipal_turnout WHERE electionId=?1")?;
+	let res = stmt.query_map([election_id], |row| {
+		Ok((row.get::<_, String>(0)?, Turnout::new(row.get(1)?, row.get(2)?, row.get(3)?)))
+	})?.collect::<SqlResult<std::collections::HashMap<String, Turnout>>>()?;
+
+	Ok(rouille::Response::json(&res))
+}
+
+pub fn issue_results(db: &Path, office_id: usize) -> Result {
+	use std::collections::HashMap;
+
+	let conn = rusqlite::Connection::open(db)?;
+
+	let (name, kind, threshold): (String, String, Option<f64>) = conn.query_row("SELECT name, kind, threshold FROM office_election WHERE id=?1", [office_id], |row| {
+		Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+	})?;
+	if kind != "issue" {
+		return Err(format!("officeId={} is not a ballot issue", office_id).into());
+	}
+	let threshold = threshold.unwrap_or(0.5);
+
+	let mut stmt = conn.prepare("SELECT candidateName, votes FROM indexed_state_results WHERE officeId=?1")?;
+	let state = stmt.query_map([office_id], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<SqlResult<Vec<(String, usize)>>>()?;
+
+	let mut counties: HashMap<String, Vec<(String, usize)>> = HashMap::new();
+	let mut stmt = conn.prepare("SELECT countyName, candidateName, votes FROM indexed_county_results WHERE officeId=?1")?;
+	let mut rows = stmt.query([office_id])?;
+	while let Some(row) = rows.next()? {
+		counties.entry(row.get(0)?).or_default().push((row.get(1)?, row.get(2)?));
+	}
+
+	let mut municipalities: HashMap<String, (String, Vec<(String, usize)>)> = HashMap::new();
+	let mut stmt = conn.prepare("SELECT municipalCode, municipalName, candidateName, votes FROM indexed_municipal_results WHERE officeId=?1")?;
+	let mut rows = stmt.query([office_id])?;
+	while let Some(row) = rows.next()? {
+		let (fips, municipality): (String, String) = (row.get(0)?, row.get(1)?);
+		municipalities.entry(fips).or_insert_with(|| (municipality, Vec::new())).1.push((row.get(2)?, row.get(3)?));
+	}
+
+	Ok(rouille::Response::json(&IssueResults {
+		threshold,
+		state: IssueResult::new(name, &state, threshold),
+		counties: counties.into_iter().map(|(county, choices)| (county.clone(), IssueResult::new(county, &choices, threshold))).collect(),
+		municipalities: municipalities.into_iter().map(|(fips, (municipality, choices))| (fips, IssueResult::new(municipality, &choices, threshold))).collect()
+	}))
+}
+
+/// The `limit` query parameter of the history endpoints: the number of most recent elections to return.
+pub fn history_limit(request: &rouille::Request) -> std::result::Result<Option<usize>, Error> {
+	match request.get_param("limit") {
+		Some(limit) => Ok(Some(limit.parse()?)),
+		None => Ok(None)
+	}
+}
+
+/// Every election in which the municipality with this FIPS code voted, most recent first, whether
+/// alone or merged with its neighbours.
+pub fn municipal_history(db: &Path, fips: String, limit: Option<usize>) -> Result {
+	let conn = rusqlite::Connection::open(db)?;
+
+	let mut stmt = conn.prepare("
+		SELECT e.id, e.name, e.date, e.map, e.kind, t.municipalCode, t.municipalName, t.registeredVoters, t.ballotsCast
+		FROM municipality_code mc JOIN indexed_municipal_turnout t ON t.municipalId = mc.municipalId JOIN election_info e ON t.electionId = e.id
+		WHERE mc.fips=?1 ORDER BY e.date DESC LIMIT ?2
+	")?;
+	let history = stmt.query_map((fips, limit.map_or(-1, |limit| limit as i64)), |row| {
+		Ok(MunicipalHistory {
+			election: Election { id: row.get(0)?, name: row.get(1)?, date: row.get(2)?, map_path: row.get(3)?, kind: row.get(4)? },
+			fips: row.get(5)?,
+			turnout: Turnout::new(row.get(6)?, row.get(7)?, row.get(8)?)
+		})
+	})?.collect::<SqlResult<Vec<MunicipalHistory>>>()?;
+
+	Ok(rouille::Response::json(&history))
+}
+
+/// The precincts of a municipality in an election, to look up their history.
+pub fn municipal_precincts(db: &Path, election_id: usize, fips: String) -> Result {
+	let conn = rusqlite::Connection::open(db)?;
+
+	let mut stmt = conn.prepare("
+		SELECT DISTINCT p.id, p.name FROM precinct p JOIN municipality_code mc ON mc.municipalId = p.municipalId JOIN municipality m ON p.municipalId = m.id
+		WHERE m.electionId=?1 AND mc.fips=?2 ORDER BY p.name
+	")?;
+	let precincts = stmt.query_map((election_id, fips), |row| {
+		Ok(Precinct { id: row.get(0)?, name: row.get(1)? })
+	})?.collect::<SqlResult<Vec<Precinct>>>()?;
+
+	Ok(rouille::Response::json(&precincts))
+}
+
+/// The same precinct in every election it was linked across, most recent first.
+pub fn precinct_history(db: &Path, precinct_id: usize, limit: Option<usize>) -> Result {
+	let conn = rusqlite::Connection::open(db)?;
+
+	let mut stmt = conn.prepare("
+		SELECT e.id, e.name, e.date, e.map, e.kind, p.id, c.name, m.name, m.fips, x.method, p.name, coalesce(p.registeredVoters, 0), coalesce(p.ballotsCast, 0)
+		FROM precinct_crosswalk x JOIN precinct p ON x.precinctId = p.id JOIN county c ON p.countyId = c.id
+		JOIN municipality m ON p.municipalId = m.id JOIN election_info e ON c.electionId = e.id
+		WHERE x.trackId = (SELECT trackId FROM precinct_crosswalk WHERE precinctId=?1) ORDER BY e.date DESC LIMIT ?2
+	")?;
+	let history = stmt.query_map((precinct_id, limit.map_or(-1, |limit| limit as i64)), |row| {
+		Ok(PrecinctHistory {
+			election: Election { id: row.get(0)?, name: row.get(1)?, date: row.get(2)?, map_path: row.get(3)?, kind: row.get(4)? },
+			id: row.get(5)?,
+			county: row.get(6)?,
+			municipality: row.get(7)?,
+			fips: row.get(8)?,
+			method: row.get(9)?,
+			turnout: Turnout::new(row.get(10)?, row.get(11)?, row.get(12)?)
+		})
+	})?.collect::<SqlResult<Vec<PrecinctHistory>>>()?;
+
+	Ok(rouille::Response::json(&history))
+}
+
 pub fn unpack(r: Result) -> rouille::Response {
 	match r {
 		Ok(r) => r,
-		Err(err) => rouille::Response::json(&err)
+		Err(err) => rouille::Response::json(&err).with_status_code(500)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn candidates(votes: &[(&str, usize)]) -> Vec<ElectionResult> {
+		votes.iter().map(|(name, votes)| ElectionResult {
+			name: name.to_string(),
+			party: None,
+			votes: *votes,
+			write_in: false,
+			ballot_share: 0.0,
+			share: 0.0
+		}).collect()
+	}
+
+	#[test]
+	fn issues_short_of_their_threshold_are_won_by_no() {
+		let issue = OfficeRules { seats: 1, threshold: Some(0.6) };
+		assert_eq!(SumElectionResult::new(candidates(&[("Yes", 550), ("No", 450)]), WriteIns::Include, issue, None).winners, ["No"]);
+		assert_eq!(SumElectionResult::new(candidates(&[("Yes", 610), ("No", 390)]), WriteIns::Include, issue, None).winners, ["Yes"]);
+		assert_eq!(SumElectionResult::new(candidates(&[("For the Tax Levy", 500), ("Against the Tax Levy", 500)]), WriteIns::Include, OfficeRules { seats: 1, threshold: Some(0.5) }, None).winners, ["Against the Tax Levy"]);
+	}
+
+	#[test]
+	fn candidate_offices_are_won_by_the_most_votes_for_each_seat() {
+		let council = OfficeRules { seats: 2, threshold: None };
+		let result = SumElectionResult::new(candidates(&[("Veronica Sims", 300), ("Elizabeth Walters", 250), ("John Schmidt", 200)]), WriteIns::Include, council, Some(400));
+		assert_eq!(result.winners, ["Veronica Sims", "Elizabeth Walters"]);
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn shares_are_of_the_ballots_cast() {
+		let council = OfficeRules { seats: 2, threshold: None };
+		let shares = |result: SumElectionResult| result.candidates.iter().map(|c| c.ballot_share).collect::<Vec<_>>();
+
+		// 400 ballots, some of which left the second seat blank
+		let result = SumElectionResult::new(candidates(&[("Veronica Sims", 300), ("Elizabeth Walters", 250), ("John Schmidt", 50)]), WriteIns::Include, council, Some(400));
+		assert_eq!((result.ballots, result.ballots_estimated), (400, false));
+		assert_eq!(shares(result), [0.75, 0.625, 0.125]);
+
+		let result = SumElectionResult::new(candidates(&[("Veronica Sims", 300), ("Elizabeth Walters", 250), ("John Schmidt", 50)]), WriteIns::Include, council, None);
+		assert_eq!((result.ballots, result.ballots_estimated), (300, true));
+		assert_eq!(shares(result), [1.0, 250.0 / 300.0, 50.0 / 300.0]);
+	}
+
+	#[test]
+	fn primary_contests_count_the_ballots_of_their_party() {
+		let conn = rusqlite::Connection::open_in_memory().unwrap();
+		crate::schema::migrate(&conn).unwrap();
+		conn.execute_batch("
+			INSERT INTO election_info(id, name, date, map, kind) VALUES(1, '2022 Primary Election', '2022-05-03', 'elections/2022/primary/map', 'primary');
+			INSERT INTO county(id, name, electionId) VALUES(1, 'Summit', 1);
+			INSERT INTO municipality(id, name, fips, electionId) VALUES(1, 'Akron', '3915301000', 1);
+			INSERT INTO precinct(id, name, municipalId, countyId, ballotsCast) VALUES(1, 'AKRON 1-A', 1, 1, 500), (2, 'AKRON 1-B', 1, 1, 400);
+			INSERT INTO precinct_party_ballots(precinctId, party, ballotsCast) VALUES(1, 'D', 300), (1, 'R', 150), (2, 'D', 250);
+			INSERT INTO office_category(id, name, electionId) VALUES(1, 'Statewide', 1);
+			INSERT INTO office_election(id, name, ballot_party, categoryId) VALUES(1, 'Governor', 'D', 1), (2, 'Governor', 'R', 1), (3, 'State Issue 1', NULL, 1);
+			INSERT INTO indexed_precinct_results(id, officeId, votes, candidateId, precinctId, isWriteIn) VALUES
+				(1, 1, 200, 1, 1, 0), (2, 1, 150, 1, 2, 0), (3, 2, 100, 2, 1, 0), (4, 2, 90, 2, 2, 0), (5, 3, 350, 3, 1, 0), (6, 3, 300, 3, 2, 0);
+		").unwrap();
+
+		assert_eq!(ballots_cast(&conn, 1, "", &[]).unwrap(), Some(550));
+		assert_eq!(ballots_cast(&conn, 2, "", &[]).unwrap(), None); // no Republican ballots were read for AKRON 1-B
+		assert_eq!(ballots_cast(&conn, 3, "", &[]).unwrap(), Some(900));
+		assert_eq!(ballots_cast(&conn, 2, "AND p.id=?2", &[&1]).unwrap(), Some(150));
+	}
+
+	#[test]
+	fn single_seats_are_shown_by_their_share_of_the_votes() {
+		let governor = OfficeRules { seats: 1, threshold: None };
+		let result = SumElectionResult::new(candidates(&[("Mike DeWine and Jon Husted", 300), ("Nan Whaley and Cheryl L. Stephens", 100)]), WriteIns::Include, governor, Some(500));
+		assert_eq!(result.candidates.iter().map(|c| (c.share, c.ballot_share)).collect::<Vec<_>>(), [(0.75, 0.6), (0.25, 0.2)]);
+
+		let council = OfficeRules { seats: 2, threshold: None };
+		let result = SumElectionResult::new(candidates(&[("Veronica Sims", 300), ("Elizabeth Walters", 100)]), WriteIns::Include, council, Some(500));
+		assert_eq!(result.candidates.iter().map(|c| c.share).collect::<Vec<_>>(), [0.6, 0.2]);
+	}
+}
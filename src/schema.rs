@@ -0,0 +1,384 @@
+use crate::{emit, Log};
+use rusqlite::Connection;
+
+/// One upgrade of the schema. A database's `PRAGMA user_version` is the number of migrations applied
+/// to it, so new migrations are only ever appended.
+pub struct Migration {
+    pub description: &'static str,
+    pub apply: fn(&Connection) -> rusqlite::Result<()>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "create the election, county, municipality, precinct, office, candidate and result tables",
+        apply: |conn| conn.execute_batch("
+            CREATE TABLE election_info(id integer primary key autoincrement, name text, date date, map text);
+            CREATE TABLE county(id integer primary key autoincrement, name text, electionId integer, foreign key (electionId) references election_info(id));
+            CREATE TABLE municipality(id integer primary key autoincrement, name text, fips text, electionId integer, foreign key (electionId) references election_info(id));
+            CREATE TABLE precinct(id integer primary key autoincrement, name text, municipalId integer, countyId integer, foreign key (municipalId) references municipality(id), foreign key (countyId) references county(id));
+            CREATE TABLE office_category(id integer primary key autoincrement, name text, electionId integer, foreign key (electionId) references election_info(id));
+            CREATE TABLE office_election(id integer primary key autoincrement, name text, categoryId integer, foreign key (categoryId) references office_category(id));
+            CREATE TABLE candidate(id integer primary key autoincrement, name text, officeId integer, foreign key (officeId) references office_election(id));
+            CREATE TABLE result(id integer primary key autoincrement, votes integer, candidateId integer, precinctId integer, foreign key (candidateId) references candidate(id), foreign key (precinctId) references precinct(id));
+
+            CREATE VIEW state_results as select r.officeId, sum(r.votes) as votes, r.candidateId, r.candidateName from county_results r group by r.candidateId;
+            CREATE VIEW municipal_results as select m.id, r.officeId, sum(r.votes) as votes, r.candidateId, r.candidateName, m.name as municipalName, m.fips as municipalCode, m.electionId from precinct_results r join municipality m on r.municipalId = m.id group by r.candidateId, m.id;
+            CREATE VIEW county_results as select c.id, r.officeId, sum(r.votes) as votes, r.candidateId, r.candidateName, c.name as countyName from precinct_results r join county c on r.countyId = c.id group by r.candidateId, c.id;
+            CREATE VIEW precinct_results as select r.id, c.officeId, r.votes, r.candidateId, c.name as candidateName, p.id as precinctId, p.name as precinctName, p.municipalId, p.countyId from result r inner join candidate c on r.candidateId = c.id inner join precinct p on r.precinctId = p.id;
+        ")
+    },
+    Migration {
+        description: "add turnout, candidate parties, write-ins, ballot issues, seats, party ballots and county FIPS codes",
+        apply: add_contest_details
+    },
+    Migration {
+        description: "link precincts across elections and record every FIPS code of a municipality",
+        apply: add_crosswalk
+    },
+    Migration {
+        description: "declare the index tables with keys and indexes so they can be refreshed one election at a time",
+        apply: |conn| conn.execute_batch("
+            DROP TABLE IF EXISTS indexed_precinct_results;
+            DROP TABLE IF EXISTS indexed_county_results;
+            DROP TABLE IF EXISTS indexed_municipal_results;
+            DROP TABLE IF EXISTS indexed_state_results;
+            DROP TABLE IF EXISTS indexed_county_turnout;
+            DROP TABLE IF EXISTS indexed_municipal_turnout;
+            DROP TABLE IF EXISTS indexed_state_turnout;
+
+            CREATE TABLE indexed_precinct_results(id integer primary key, officeId integer not null, votes integer not null, candidateId integer not null, candidateName text, precinctId integer not null, precinctName text, municipalId integer, countyId integer, candidateParty text, isWriteIn integer not null);
+            CREATE INDEX indexed_precinct_results_office on indexed_precinct_results(officeId);
+            CREATE INDEX indexed_precinct_results_candidate on indexed_precinct_results(candidateId);
+            CREATE TABLE indexed_county_results(id integer not null, officeId integer not null, votes integer not null, candidateId integer not null, candidateName text, countyName text, candidateParty text, isWriteIn integer not null, primary key (candidateId, id));
+            CREATE INDEX indexed_county_results_office on indexed_county_results(officeId, countyName);
+            CREATE TABLE indexed_municipal_results(id integer not null, officeId integer not null, votes integer not null, candidateId integer not null, candidateName text, municipalName text, municipalCode text, electionId integer not null, candidateParty text, isWriteIn integer not null, primary key (candidateId, id));
+            CREATE INDEX indexed_municipal_results_office on indexed_municipal_results(officeId, municipalCode);
+            CREATE TABLE indexed_state_results(officeId integer not null, votes integer not null, candidateId integer primary key, candidateName text, candidateParty text, isWriteIn integer not null);
+            CREATE INDEX indexed_state_results_office on indexed_state_results(officeId);
+
+            CREATE TABLE indexed_state_turnout(electionId integer primary key, registeredVoters integer not null, ballotsCast integer not null);
+            CREATE TABLE indexed_municipal_turnout(municipalId integer primary key, municipalName text, municipalCode text, electionId integer not null, registeredVoters integer not null, ballotsCast integer not null);
+            CREATE INDEX indexed_municipal_turnout_election on indexed_municipal_turnout(electionId);
+            CREATE TABLE indexed_county_turnout(countyId integer primary key, countyName text, electionId integer not null, registeredVoters integer not null, ballotsCast integer not null);
+            CREATE INDEX indexed_county_turnout_election on indexed_county_turnout(electionId);
+
+            CREATE INDEX result_candidate on result(candidateId);
+
+            -- read from the precincts rather than the grouped county_results, which filters are not pushed through
+            DROP VIEW state_results;
+            CREATE VIEW state_results as select r.officeId, sum(r.votes) as votes, r.candidateId, r.candidateName, r.candidateParty, r.isWriteIn from precinct_results r join county c on r.countyId = c.id group by r.candidateId;
+        ")
+    },
+    Migration {
+        description: "index the columns foreign keys are declared on, so enforcing them does not scan whole tables",
+        apply: |conn| conn.execute_batch("
+            CREATE INDEX county_election on county(electionId);
+            CREATE INDEX municipality_election on municipality(electionId);
+            CREATE INDEX precinct_municipality on precinct(municipalId);
+            CREATE INDEX precinct_county on precinct(countyId);
+            CREATE INDEX office_category_election on office_category(electionId);
+            CREATE INDEX office_election_category on office_election(categoryId);
+            CREATE INDEX candidate_office on candidate(officeId);
+            CREATE INDEX result_precinct on result(precinctId);
+            CREATE INDEX municipality_code_municipality on municipality_code(municipalId);
+            CREATE INDEX precinct_crosswalk_previous on precinct_crosswalk(previousId);
+        ")
+    },
+    Migration {
+        description: "store candidate parties as the codes party ballots use, e.g. G rather than GRN",
+        apply: normalize_parties
+    },
+    Migration {
+        description: "record the ballots cast for each party in primaries",
+        apply: |conn| conn.execute_batch("
+            CREATE TABLE precinct_party_ballots(precinctId integer not null, party text not null, ballotsCast integer not null check (typeof(ballotsCast) = 'integer' and ballotsCast >= 0), primary key (precinctId, party), foreign key (precinctId) references precinct(id));
+        ")
+    },
+];
+
+/// The version of a database with every migration applied.
+pub fn latest_version() -> usize {
+    MIGRATIONS.len()
+}
+
+/// The number of migrations applied to the database. Databases made before versioning hold the
+/// first migration's tables with a `user_version` of 0.
+pub fn version(conn: &Connection) -> rusqlite::Result<usize> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let tables: i64 = conn.query_row("SELECT count(*) FROM sqlite_master WHERE type='table' AND name='election_info'", [], |row| row.get(0))?;
+    Ok(if version == 0 && tables > 0 { 1 } else { version })
+}
+
+/// Applies every migration the database is missing, returning the version it started from.
+pub fn migrate(conn: &Connection) -> Result<usize, String> {
+    use colored::Colorize;
+
+    let from = version(conn).map_err(|why| format!("Failed to read the schema version: {}", why.to_string().underline()))?;
+    if from > latest_version() {
+        return Err(format!("The database has schema version {}, newer than the {} this oev-tool knows; update oev-tool", from, latest_version()));
+    }
+
+    for (idx, migration) in MIGRATIONS.iter().enumerate().skip(from) {
+        println!("Migrating to version {}: {}", idx + 1, migration.description);
+        (migration.apply)(conn)
+            .and_then(|_| conn.execute_batch(&format!("PRAGMA user_version = {}", idx + 1)))
+            .map_err(|why| format!("Failed to migrate to version {}: {}", idx + 1, why.to_string().underline()))?;
+    }
+
+    Ok(from)
+}
+
+/// Reports whether the database has every migration applied, explaining how to fix it if not.
+pub fn is_current(conn: &Connection) -> bool {
+    use colored::Colorize;
+
+    match version(conn) {
+        Ok(version) if version == latest_version() => true,
+        Ok(version) => {
+            emit(Log::Error(format!("The database has schema version {} but version {} is required", version, latest_version())));
+            emit(Log::Info(format!("run the {} module", "migrate".underline())));
+            false
+        },
+        Err(why) => {
+            emit(Log::Error(format!("Failed to read the schema version: {}", why.to_string().underline())));
+            false
+        }
+    }
+}
+
+fn add_contest_details(conn: &Connection) -> rusqlite::Result<()> {
+    use crate::converter;
+
+    // the rebuilt result table only takes whole vote counts; anything else was stored by an importer
+    // that did not validate counts, and is listed for the workbook to be corrected and re-imported
+    let invalid = conn.prepare("
+        SELECT r.id, r.votes, e.name, o.name, c.name, p.name FROM result r
+        LEFT JOIN candidate c ON r.candidateId = c.id LEFT JOIN office_election o ON c.officeId = o.id
+        LEFT JOIN office_category oc ON o.categoryId = oc.id LEFT JOIN election_info e ON oc.electionId = e.id
+        LEFT JOIN precinct p ON r.precinctId = p.id
+        WHERE typeof(r.votes) != 'integer' OR r.votes < 0
+        ORDER BY r.id
+    ")?.query_map([], |row| {
+        let votes: rusqlite::types::Value = row.get(1)?;
+        let votes = match votes {
+            rusqlite::types::Value::Null => "nothing".to_string(),
+            rusqlite::types::Value::Integer(votes) => votes.to_string(),
+            rusqlite::types::Value::Real(votes) => votes.to_string(),
+            rusqlite::types::Value::Text(votes) => format!("\"{}\"", votes),
+            rusqlite::types::Value::Blob(_) => "a blob".to_string(),
+        };
+        let (id, election, office, candidate, precinct): (i64, Option<String>, Option<String>, Option<String>, Option<String>) = (row.get(0)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?);
+        let unknown = || "?".to_string();
+        Ok(format!("result id={} ({}: {} for {} in precinct {}) has votes {}", id, election.unwrap_or_else(unknown), candidate.unwrap_or_else(unknown), office.unwrap_or_else(unknown), precinct.unwrap_or_else(unknown), votes))
+    })?.collect::<rusqlite::Result<Vec<String>>>()?;
+
+    if !invalid.is_empty() {
+        for result in &invalid {
+            emit(Log::Error(result));
+        }
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT_CHECK),
+            Some(format!("{} results have votes that are not whole numbers of at least 0; correct them or delete their elections and migrate again", invalid.len()))
+        ));
+    }
+
+    // the views name the tables they read, so they are recreated around the rebuilt result table
+    conn.execute_batch("
+        DROP VIEW state_results;
+        DROP VIEW municipal_results;
+        DROP VIEW county_results;
+        DROP VIEW precinct_results;
+
+        ALTER TABLE election_info ADD COLUMN kind text not null default 'general';
+        ALTER TABLE county ADD COLUMN fips text;
+        ALTER TABLE precinct ADD COLUMN registeredVoters integer;
+        ALTER TABLE precinct ADD COLUMN ballotsCast integer;
+        ALTER TABLE office_category ADD COLUMN ballot_party text;
+        ALTER TABLE office_election ADD COLUMN kind text not null default 'candidate';
+        ALTER TABLE office_election ADD COLUMN threshold real;
+        ALTER TABLE office_election ADD COLUMN seats integer not null default 1;
+        ALTER TABLE office_election ADD COLUMN ballot_party text;
+        ALTER TABLE candidate ADD COLUMN party text;
+        ALTER TABLE candidate ADD COLUMN is_write_in integer not null default 0;
+
+        CREATE TABLE result_checked(id integer primary key autoincrement, votes integer not null check (typeof(votes) = 'integer' and votes >= 0), candidateId integer, precinctId integer, foreign key (candidateId) references candidate(id), foreign key (precinctId) references precinct(id));
+        INSERT INTO result_checked(id, votes, candidateId, precinctId) SELECT id, votes, candidateId, precinctId FROM result;
+        DROP TABLE result;
+        ALTER TABLE result_checked RENAME TO result;
+
+        CREATE VIEW state_results as select r.officeId, sum(r.votes) as votes, r.candidateId, r.candidateName, r.candidateParty, r.isWriteIn from county_results r group by r.candidateId;
+        CREATE VIEW municipal_results as select m.id, r.officeId, sum(r.votes) as votes, r.candidateId, r.candidateName, m.name as municipalName, m.fips as municipalCode, m.electionId, r.candidateParty, r.isWriteIn from precinct_results r join municipality m on r.municipalId = m.id group by r.candidateId, m.id;
+        CREATE VIEW county_results as select c.id, r.officeId, sum(r.votes) as votes, r.candidateId, r.candidateName, c.name as countyName, r.candidateParty, r.isWriteIn from precinct_results r join county c on r.countyId = c.id group by r.candidateId, c.id;
+        CREATE VIEW precinct_results as select r.id, c.officeId, r.votes, r.candidateId, c.name as candidateName, p.id as precinctId, p.name as precinctName, p.municipalId, p.countyId, c.party as candidateParty, c.is_write_in as isWriteIn from result r inner join candidate c on r.candidateId = c.id inner join precinct p on r.precinctId = p.id;
+
+        CREATE VIEW state_turnout as select t.electionId, sum(t.registeredVoters) as registeredVoters, sum(t.ballotsCast) as ballotsCast from county_turnout t group by t.electionId;
+        CREATE VIEW municipal_turnout as select m.id as municipalId, m.name as municipalName, m.fips as municipalCode, m.electionId, coalesce(sum(p.registeredVoters), 0) as registeredVoters, coalesce(sum(p.ballotsCast), 0) as ballotsCast from municipality m join precinct p on p.municipalId = m.id group by m.id;
+        CREATE VIEW county_turnout as select c.id as countyId, c.name as countyName, c.electionId, coalesce(sum(p.registeredVoters), 0) as registeredVoters, coalesce(sum(p.ballotsCast), 0) as ballotsCast from county c join precinct p on p.countyId = c.id group by c.id;
+    ")?;
+
+    // what the importer now reads from the workbooks is recovered from the names already stored
+    for county in crate::counties::COUNTIES.iter() {
+        conn.execute("UPDATE county SET fips=?1 WHERE name=?2 COLLATE NOCASE", (county.fips, county.name))?;
+    }
+
+    let elections = conn.prepare("SELECT id, name FROM election_info")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<Vec<(i64, String)>>>()?;
+    for (id, name) in elections {
+        conn.execute("UPDATE election_info SET kind=?1 WHERE id=?2", (converter::ElectionKind::detect(&name).as_str(), id))?;
+    }
+
+    let candidates = conn.prepare("SELECT id, name FROM candidate")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<Vec<(i64, String)>>>()?;
+    for (id, header) in candidates {
+        if let (name, Some(party)) = converter::split_party(&header) {
+            conn.execute("UPDATE candidate SET name=?1, party=?2 WHERE id=?3", (name, party, id))?;
+        }
+    }
+
+    let offices = conn.prepare("SELECT id, name FROM office_election")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<Vec<(i64, String)>>>()?;
+    for (id, name) in offices {
+        let candidates = conn.prepare_cached("SELECT name FROM candidate WHERE officeId=?1")?
+            .query_map([id], |row| row.get(0))?.collect::<rusqlite::Result<Vec<String>>>()?;
+        conn.execute("UPDATE office_election SET seats=?1 WHERE id=?2", (converter::parse_seats(&name), id))?;
+        if converter::is_issue(&candidates) {
+            conn.execute("UPDATE office_election SET kind='issue', threshold=0.5 WHERE id=?1", [id])?;
+        }
+    }
+
+    Ok(())
+}
+
+fn normalize_parties(conn: &Connection) -> rusqlite::Result<()> {
+    let parties = conn.prepare("SELECT DISTINCT party FROM candidate WHERE party IS NOT NULL")?
+        .query_map([], |row| row.get(0))?.collect::<rusqlite::Result<Vec<String>>>()?;
+    for party in parties {
+        let Some(code) = crate::converter::party_code(&party).filter(|code| *code != party) else { continue };
+        conn.execute("UPDATE candidate SET party=?1 WHERE party=?2", (code, &party))?;
+        for table in ["indexed_precinct_results", "indexed_county_results", "indexed_municipal_results", "indexed_state_results"] {
+            conn.execute(&format!("UPDATE {} SET candidateParty=?1 WHERE candidateParty=?2", table), (code, &party))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn add_crosswalk(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("
+        CREATE TABLE municipality_code(municipalId integer, fips text not null, foreign key (municipalId) references municipality(id));
+        CREATE INDEX municipality_code_fips on municipality_code(fips);
+        CREATE TABLE precinct_alias(precinctId integer primary key, previousName text not null, method text not null check (method in ('alias', 'manual')), foreign key (precinctId) references precinct(id));
+        CREATE TABLE precinct_crosswalk(precinctId integer primary key, previousId integer, trackId integer not null, method text check (method in ('name', 'alias', 'manual')), foreign key (precinctId) references precinct(id), foreign key (previousId) references precinct(id));
+        CREATE INDEX precinct_crosswalk_track on precinct_crosswalk(trackId);
+    ")?;
+
+    let municipalities = conn.prepare("SELECT id, fips FROM municipality")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<Vec<(i64, String)>>>()?;
+    for (id, fips) in municipalities {
+        crate::crosswalk::save_municipal_codes(conn, id, &fips)?;
+    }
+
+    crate::crosswalk::link_precincts(conn, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A database as the importer wrote it before versioning: votes were inserted as the text of
+    /// their cells and the index tables were copies of the views.
+    fn baseline(votes: &[&str]) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        (MIGRATIONS[0].apply)(&conn).unwrap();
+        conn.execute_batch("
+            INSERT INTO election_info(name, date, map) VALUES('2022 General Election', '2022-11-08', 'elections/2022/general/map');
+            INSERT INTO county(name, electionId) VALUES('Summit', 1);
+            INSERT INTO municipality(name, fips, electionId) VALUES('Akron', '3915301000', 1);
+            INSERT INTO precinct(name, municipalId, countyId) VALUES('AKRON 1-A', 1, 1);
+            INSERT INTO precinct(name, municipalId, countyId) VALUES('AKRON 1-B', 1, 1);
+            INSERT INTO office_category(name, electionId) VALUES('Statewide', 1);
+            INSERT INTO office_election(name, categoryId) VALUES('Governor and Lieutenant Governor', 1);
+            INSERT INTO office_election(name, categoryId) VALUES('Summit County Council At Large (Vote for not more than 3)', 1);
+            INSERT INTO office_election(name, categoryId) VALUES('Issue 1 - Constitutional Amendment', 1);
+            INSERT INTO candidate(name, officeId) VALUES('Mike DeWine and Jon Husted (R)', 1);
+            INSERT INTO candidate(name, officeId) VALUES('Nan Whaley and Cheryl L. Stephens (D)', 1);
+            INSERT INTO candidate(name, officeId) VALUES('Veronica Sims (D)', 2);
+            INSERT INTO candidate(name, officeId) VALUES('Yes', 3);
+            INSERT INTO candidate(name, officeId) VALUES('No', 3);
+        ").unwrap();
+        for (idx, votes) in votes.iter().enumerate() {
+            conn.execute("INSERT INTO result(votes, candidateId, precinctId) VALUES(?1, ?2, ?3)", (votes, idx % 5 + 1, idx / 5 + 1)).unwrap();
+        }
+        conn.execute_batch("
+            CREATE TABLE indexed_precinct_results AS SELECT * FROM precinct_results;
+            CREATE TABLE indexed_county_results AS SELECT * FROM county_results;
+            CREATE TABLE indexed_municipal_results AS SELECT * FROM municipal_results;
+            CREATE TABLE indexed_state_results AS SELECT * FROM state_results;
+        ").unwrap();
+
+        conn
+    }
+
+    #[test]
+    fn baseline_databases_migrate_with_their_data() {
+        let conn = baseline(&["412", "388", "301", "520", "280", "97", "143", "88", "150", "75"]);
+        assert_eq!(version(&conn).unwrap(), 1);
+
+        assert_eq!(migrate(&conn), Ok(1));
+        assert_eq!(version(&conn).unwrap(), latest_version());
+
+        let votes: Vec<(String, i64)> = conn.prepare("SELECT typeof(votes), votes FROM result ORDER BY id").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().collect::<rusqlite::Result<_>>().unwrap();
+        assert!(votes.iter().all(|(kind, _)| kind == "integer"));
+        assert_eq!(votes.iter().map(|(_, votes)| votes).sum::<i64>(), 2454);
+
+        let candidate: (String, Option<String>) = conn.query_row("SELECT name, party FROM candidate WHERE id=1", [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!(candidate, ("Mike DeWine and Jon Husted".to_string(), Some("R".to_string())));
+        let offices: Vec<(String, u32, Option<f64>)> = conn.prepare("SELECT kind, seats, threshold FROM office_election ORDER BY id").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap().collect::<rusqlite::Result<_>>().unwrap();
+        assert_eq!(offices, [("candidate".to_string(), 1, None), ("candidate".to_string(), 3, None), ("issue".to_string(), 1, Some(0.5))]);
+
+        let fips: String = conn.query_row("SELECT fips FROM county WHERE id=1", [], |row| row.get(0)).unwrap();
+        assert_eq!(fips, "39153");
+        let kind: String = conn.query_row("SELECT kind FROM election_info WHERE id=1", [], |row| row.get(0)).unwrap();
+        assert_eq!(kind, "general");
+        let codes: i64 = conn.query_row("SELECT count(*) FROM municipality_code", [], |row| row.get(0)).unwrap();
+        assert_eq!(codes, 1);
+        let linked: i64 = conn.query_row("SELECT count(*) FROM precinct_crosswalk", [], |row| row.get(0)).unwrap();
+        assert_eq!(linked, 2);
+    }
+
+    #[test]
+    fn stored_party_abbreviations_become_party_codes() {
+        let conn = baseline(&["412", "388", "301", "520", "280"]);
+        migrate(&conn).unwrap();
+        conn.execute_batch("
+            UPDATE candidate SET party='GRN' WHERE id=1;
+            UPDATE candidate SET party='NAT' WHERE id=2;
+            INSERT INTO indexed_state_results(officeId, votes, candidateId, candidateName, candidateParty, isWriteIn) VALUES(1, 412, 1, 'Mike DeWine and Jon Husted', 'GRN', 0);
+        ").unwrap();
+
+        normalize_parties(&conn).unwrap();
+        let parties: Vec<Option<String>> = conn.prepare("SELECT party FROM candidate ORDER BY id LIMIT 3").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().collect::<rusqlite::Result<_>>().unwrap();
+        assert_eq!(parties, [Some("G".to_string()), Some("NAT".to_string()), Some("D".to_string())]);
+        let indexed: String = conn.query_row("SELECT candidateParty FROM indexed_state_results WHERE candidateId=1", [], |row| row.get(0)).unwrap();
+        assert_eq!(indexed, "G");
+    }
+
+    #[test]
+    fn votes_that_are_not_whole_numbers_stop_the_migration() {
+        let mut conn = baseline(&["412", "1,204", "301", "-", "280"]);
+
+        let savepoint = conn.savepoint().unwrap();
+        let why = migrate(&savepoint).unwrap_err();
+        assert!(why.contains("version 2"), "{}", why);
+        assert!(why.contains("2 results"), "{}", why);
+        drop(savepoint);
+
+        assert_eq!(version(&conn).unwrap(), 1);
+        let votes: Vec<String> = conn.prepare("SELECT CAST(votes AS text) FROM result ORDER BY id").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().collect::<rusqlite::Result<_>>().unwrap();
+        assert_eq!(votes, ["412", "1,204", "301", "-", "280"]);
+    }
+}
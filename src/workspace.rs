@@ -0,0 +1,41 @@
+use std::path::{Path, PathBuf};
+
+/// Where oev-tool keeps its files: the database, and the project directory holding `elections/`,
+/// `maps/`, `public/` and the files import-election leaves for finalize-map. Paths given on the
+/// command line are relative to the root, so the tool can be run from any directory.
+#[derive(Clone, Debug)]
+pub struct Workspace {
+    pub root: PathBuf,
+    pub db: PathBuf,
+}
+
+impl Workspace {
+    /// The database defaults to `elections.db` in the root.
+    pub fn new(root: PathBuf, db: Option<PathBuf>) -> Workspace {
+        let db = db.unwrap_or_else(|| root.join("elections.db"));
+        Workspace { root, db }
+    }
+
+    /// Resolves a path relative to the root; absolute paths are kept as they are.
+    pub fn path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// The municipalities import-election found no precincts for, read by finalize-map.
+    pub fn filter_file(&self) -> PathBuf {
+        self.path("map-filter.temp")
+    }
+
+    /// The municipalities import-election merged, read by finalize-map.
+    pub fn merge_file(&self) -> PathBuf {
+        self.path("map-merge.temp")
+    }
+
+    pub fn index_page(&self) -> PathBuf {
+        self.path("public/index.html")
+    }
+
+    pub fn county_map(&self) -> PathBuf {
+        self.path("maps/county")
+    }
+}